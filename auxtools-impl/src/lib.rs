@@ -58,26 +58,92 @@ fn extract_args(a: &syn::FnArg) -> &syn::PatType {
 	}
 }
 
+/// The `init` attribute registers a function to run when auxtools is initialized.
+///
+/// `full` functions run when auxtools is loaded for the first time and `partial` ones every time it's initialized.
+/// Functions with a higher `priority` run first (the default is 0), and `after` (which can be repeated) names
+/// functions of the same kind that have to run first, by name or the end of their path. A name that matches more
+/// than one function is an error. Anything depending on a failed function is skipped.
+///
+/// # Examples
+/// ```ignore
+/// #[init(partial, priority = 10)]
+/// fn load_config() -> Result<(), String> {
+///     Ok(())
+/// }
+///
+/// #[init(partial, after = "load_config")]
+/// fn setup_cache() -> Result<(), String> {
+///     Ok(())
+/// }
+/// ```
 #[proc_macro_attribute]
 pub fn init(attr: TokenStream, item: TokenStream) -> TokenStream {
-	let init_type = syn::parse_macro_input!(attr as syn::Ident);
+	let args = syn::parse_macro_input!(attr as syn::AttributeArgs);
 	let func = syn::parse_macro_input!(item as syn::ItemFn);
 	let func_name = &func.sig.ident;
 
-	let func_type = match init_type.to_string().as_str() {
-		"full" => quote! { auxtools::FullInitFunc },
-		"partial" => quote! { auxtools::PartialInitFunc },
-		_ => {
-			return syn::Error::new(init_type.span(), "invalid init type")
-				.to_compile_error()
-				.into()
+	let mut func_type = None;
+	let mut priority = quote! { 0 };
+	let mut after = vec![];
+
+	for arg in &args {
+		match arg {
+			syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("full") => {
+				func_type = Some(quote! { auxtools::FullInitFunc });
+			}
+			syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("partial") => {
+				func_type = Some(quote! { auxtools::PartialInitFunc });
+			}
+			syn::NestedMeta::Meta(syn::Meta::NameValue(nv)) if nv.path.is_ident("priority") => {
+				match &nv.lit {
+					Lit::Int(lit) => priority = quote! { #lit },
+					_ => {
+						return syn::Error::new(nv.lit.span(), "priority must be an integer")
+							.to_compile_error()
+							.into()
+					}
+				}
+			}
+			syn::NestedMeta::Meta(syn::Meta::NameValue(nv)) if nv.path.is_ident("after") => {
+				match &nv.lit {
+					Lit::Str(lit) => after.push(lit.clone()),
+					_ => {
+						return syn::Error::new(nv.lit.span(), "after must be a string")
+							.to_compile_error()
+							.into()
+					}
+				}
+			}
+			_ => {
+				return syn::Error::new(arg.span(), "invalid init argument")
+					.to_compile_error()
+					.into()
+			}
+		}
+	}
+
+	let func_type = match func_type {
+		Some(func_type) => func_type,
+		None => {
+			return syn::Error::new(
+				proc_macro2::Span::call_site(),
+				"init type must be `full` or `partial`",
+			)
+			.to_compile_error()
+			.into()
 		}
 	};
 
 	let inventory_define = quote! {
 		auxtools::inventory::submit!(
 			#![crate = auxtools]
-			#func_type(#func_name)
+			#func_type(auxtools::InitFuncInfo {
+				func: #func_name,
+				name: concat!(module_path!(), "::", stringify!(#func_name)),
+				priority: #priority,
+				after: &[#(#after),*],
+			})
 		);
 	};
 
@@ -180,7 +246,7 @@ pub fn hook(attr: TokenStream, item: TokenStream) -> TokenStream {
 		Some(p) => quote! {
 			auxtools::inventory::submit!(
 				#![crate = auxtools]
-				auxtools::CompileTimeHook::new(#p, #func_name)
+				auxtools::CompileTimeHook::with_name(#p, #func_name, stringify!(#func_name))
 			);
 		},
 		None => quote! {},
@@ -1,5 +1,4 @@
-use auxtools::*;
-use dmasm;
+use crate::*;
 
 pub struct AssembleEnv;
 
@@ -33,6 +32,11 @@ impl dmasm::assembler::AssembleEnv for AssembleEnv {
 	}
 
 	fn get_proc_index(&mut self, path: &str) -> Option<u32> {
+		// Custom opcodes are written as calls, and BytecodePatch swaps them in after assembly
+		if let Some(id) = custom_opcodes::proc_id(path) {
+			return Some(id);
+		}
+
 		Proc::find(path).map(|p| p.id.0)
 	}
 
@@ -66,10 +66,26 @@ pub fn byond_return(value: Option<Vec<u8>>) -> *const c_char {
 	}
 }
 
+// Used by byond_ffi_fn! so that a panic doesn't unwind into BYOND
+#[doc(hidden)]
+pub fn byond_return_catching<F: FnOnce() -> Option<Vec<u8>>>(name: &str, f: F) -> *const c_char {
+	match crate::panics::catch(f) {
+		Ok(value) => byond_return(value),
+		Err(e) => {
+			let e = e.with_proc(name);
+			let first_line = e.message.lines().next().unwrap_or_default().to_owned();
+			crate::runtime::report_runtime(&e);
+			byond_return(Some(format!("FAILED ({})", first_line).into_bytes()))
+		}
+	}
+}
+
 /// Creates a normal byond ffi function that can be called in DM with [call](http://www.byond.com/docs/ref/#/proc/call).
 ///
 /// You should favour [hooks](attr.hook.html) over these when working with auxtools.
 ///
+/// A panic is reported as a runtime and the function returns `"FAILED (<panic message>)"`.
+///
 /// # Examples
 /// ```ignore
 /// // byond_ffi_fn! { my_proc(_input) {
@@ -85,7 +101,7 @@ macro_rules! byond_ffi_fn {
             _argc: ::std::os::raw::c_int, _argv: *const *const ::std::os::raw::c_char
         ) -> *const ::std::os::raw::c_char {
             let closure = || ($body);
-            $crate::byond_ffi::byond_return(closure().map(From::from))
+            $crate::byond_ffi::byond_return_catching(stringify!($name), || closure().map(From::from))
         }
     };
 
@@ -110,7 +126,7 @@ macro_rules! byond_ffi_fn {
             )?
 
             let closure = || ($body);
-            $crate::byond_ffi::byond_return(closure().map(From::from))
+            $crate::byond_ffi::byond_return_catching(stringify!($name), || closure().map(From::from))
         }
     };
 }
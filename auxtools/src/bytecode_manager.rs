@@ -15,6 +15,8 @@ static mut BYTECODE_ALLOCATIONS: UnsafeCell<Option<State>> = UnsafeCell::new(Non
 struct State {
 	allocations: HashSet<Vec<u32>>,
 	original: HashMap<raw_types::procs::ProcId, (*mut u32, u16)>,
+	original_locals:
+		HashMap<raw_types::misc::LocalsId, (*const raw_types::strings::VariableId, u16)>,
 }
 
 pub fn init() {
@@ -23,6 +25,7 @@ pub fn init() {
 		*ptr = Some(State {
 			allocations: HashSet::new(),
 			original: HashMap::new(),
+			original_locals: HashMap::new(),
 		});
 	}
 }
@@ -60,6 +63,10 @@ pub fn shutdown() {
 		}
 	}
 
+	for (id, (ptr, count)) in state.original_locals {
+		raw_types::misc::set_locals(id, ptr, count);
+	}
+
 	for mut vec in state.allocations {
 		// If a proc with this bytecode is still running, just leak the mrmoy
 		if active_ptrs.contains(&vec.as_mut_ptr()) {
@@ -68,7 +75,61 @@ pub fn shutdown() {
 	}
 }
 
-pub fn set_bytecode(proc: &Proc, mut bytecode: Vec<u32>) {
+/// Puts a proc's original bytecode back. Does nothing if it was never replaced.
+pub fn reset_bytecode(proc: &Proc) {
+	let state = unsafe {
+		let ptr = BYTECODE_ALLOCATIONS.get();
+		(*ptr).as_mut().unwrap()
+	};
+
+	// The replacement stays allocated in case the proc is still running it
+	if let Some((ptr, len)) = state.original.remove(&proc.id) {
+		instruction_hooks::unhook_proc(proc);
+
+		unsafe {
+			raw_types::misc::set_bytecode((*proc.entry).bytecode, ptr, len);
+		}
+	}
+}
+
+pub fn get_original_bytecode(proc: &Proc) -> (*mut u32, u16) {
+	let state = unsafe {
+		let ptr = BYTECODE_ALLOCATIONS.get();
+		(*ptr).as_mut().unwrap()
+	};
+
+	match state.original.get(&proc.id) {
+		Some(original) => *original,
+		None => unsafe { proc.bytecode_mut_ptr() },
+	}
+}
+
+/// Keeps `bytecode` alive until shutdown and returns a pointer to it that can be passed to [swap_bytecode].
+pub fn allocate(mut bytecode: Vec<u32>) -> (*mut u32, u16) {
+	let state = unsafe {
+		let ptr = BYTECODE_ALLOCATIONS.get();
+		(*ptr).as_mut().unwrap()
+	};
+
+	let len = bytecode.len();
+
+	let ptr = match state.allocations.get(&bytecode) {
+		Some(bytecode) => {
+			bytecode.as_ptr() as *mut u32 // don't @ me
+		}
+
+		None => {
+			let ptr = bytecode.as_mut_ptr();
+			state.allocations.insert(bytecode);
+			ptr
+		}
+	};
+
+	(ptr, u16::try_from(len).unwrap())
+}
+
+/// Points a proc at bytecode from [allocate]. This is cheap enough to do on every call.
+pub fn swap_bytecode(proc: &Proc, ptr: *mut u32, len: u16) {
 	let state = unsafe {
 		let ptr = BYTECODE_ALLOCATIONS.get();
 		(*ptr).as_mut().unwrap()
@@ -80,27 +141,49 @@ pub fn set_bytecode(proc: &Proc, mut bytecode: Vec<u32>) {
 		state.original.insert(proc.id, (ptr, len));
 	}
 
-	let (ptr, len) = {
-		let len = bytecode.len();
+	unsafe {
+		raw_types::misc::set_bytecode((*proc.entry).bytecode, ptr, len);
+	}
+}
 
-		let ptr = match state.allocations.get(&bytecode) {
-			Some(bytecode) => {
-				bytecode.as_ptr() as *mut u32 // don't @ me
-			}
+pub fn set_bytecode(proc: &Proc, bytecode: Vec<u32>) {
+	// Hooks are tied to offsets in the old bytecode
+	instruction_hooks::unhook_proc(proc);
 
-			None => {
-				let ptr = bytecode.as_mut_ptr();
-				state.allocations.insert(bytecode);
-				ptr
-			}
-		};
+	let (ptr, len) = allocate(bytecode);
+	swap_bytecode(proc, ptr, len);
+}
 
-		(ptr, len)
+/// Appends `names` to the locals table `proc` uses, so replacement bytecode has room for more locals than the
+/// original. Returns the index of the first new local, or None if the table would be too big.
+///
+/// Procs can share a locals table, so it only grows until shutdown: shrinking it could leave another proc's
+/// replacement without room for its locals. Frames that are already running don't care either way, they remember
+/// how many locals they started with.
+pub fn add_locals(proc: &Proc, names: &[raw_types::strings::VariableId]) -> Option<u32> {
+	let state = unsafe {
+		let ptr = BYTECODE_ALLOCATIONS.get();
+		(*ptr).as_mut().unwrap()
 	};
 
-	let len = u16::try_from(len).unwrap();
+	let id = unsafe { (*proc.entry).locals };
+	let (ptr, count) = raw_types::misc::get_locals(id);
 
-	unsafe {
-		raw_types::misc::set_bytecode((*proc.entry).bytecode, ptr, len);
+	let mut table = vec![];
+	if count != 0 {
+		table.extend_from_slice(unsafe { std::slice::from_raw_parts(ptr, count) });
 	}
+	table.extend_from_slice(names);
+
+	let new_count = u16::try_from(table.len()).ok()?;
+	state
+		.original_locals
+		.entry(id)
+		.or_insert((ptr, count as u16));
+
+	// Leaked, because the debugger can still look up the names of a frame that started with this table
+	let table = Box::leak(table.into_boxed_slice());
+	raw_types::misc::set_locals(id, table.as_ptr(), new_count);
+
+	Some(count as u32)
 }
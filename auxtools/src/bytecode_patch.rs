@@ -0,0 +1,210 @@
+use crate::custom_opcodes::{self, CustomOpcode};
+use crate::*;
+use dmasm::{Instruction, Node};
+
+#[derive(Debug)]
+pub enum PatchError {
+	Disassembly(String),
+	Assembly(String),
+	InvalidIndex(usize),
+}
+
+impl std::fmt::Display for PatchError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Disassembly(e) => write!(f, "couldn't disassemble bytecode: {}", e),
+			Self::Assembly(e) => write!(f, "couldn't assemble bytecode: {}", e),
+			Self::InvalidIndex(idx) => write!(f, "no instruction at index {}", idx),
+		}
+	}
+}
+
+/// A disassembled proc that can be edited and installed back into BYOND.
+///
+/// Jumps refer to labels rather than offsets, so they stay correct as instructions are moved around.
+/// Instruction indices only count instructions, not labels or comments.
+///
+/// # Examples
+///
+/// Removes the first `DbgLine` from a proc.
+/// ```ignore
+/// let proc = Proc::find("/proc/do_explode").unwrap();
+/// let mut patch = BytecodePatch::new(&proc)?;
+///
+/// if let Some(idx) = patch.position(|ins| matches!(ins, dmasm::Instruction::DbgLine(_))) {
+///     patch.remove(idx)?;
+/// }
+///
+/// patch.install(&proc)?;
+/// ```
+#[derive(Clone)]
+pub struct BytecodePatch {
+	nodes: Vec<Node>,
+}
+
+impl BytecodePatch {
+	/// Disassembles the bytecode a proc is currently running, as it was before any instruction hooks were added.
+	///
+	/// Custom opcodes from an earlier patch show up as the calls they were assembled from, so they're kept.
+	pub fn new(proc: &Proc) -> Result<Self, PatchError> {
+		Self::from_bytecode(&instruction_hooks::unhooked_bytecode(proc))
+	}
+
+	/// Disassembles the bytecode a proc was compiled with, ignoring any earlier patches and instruction hooks.
+	pub fn from_original(proc: &Proc) -> Result<Self, PatchError> {
+		Self::from_bytecode(&instruction_hooks::unhooked_original_bytecode(proc))
+	}
+
+	/// Disassembles raw bytecode. It can't contain instruction hooks or custom opcodes, so use
+	/// [new](#method.new) for anything taken from a proc.
+	pub fn from_bytecode(bytecode: &[u32]) -> Result<Self, PatchError> {
+		let mut env = DisassembleEnv;
+		let (nodes, error) = dmasm::disassembler::disassemble(bytecode, &mut env);
+
+		if let Some(error) = error {
+			return Err(PatchError::Disassembly(format!("{:?}", error)));
+		}
+
+		// We don't care about where things used to be
+		let nodes = nodes
+			.into_iter()
+			.map(|node| match node {
+				Node::Instruction(ins, _) => Node::Instruction(ins, ()),
+				Node::Label(name) => Node::Label(name),
+				Node::Comment(comment) => Node::Comment(comment),
+			})
+			.collect();
+
+		Ok(Self { nodes })
+	}
+
+	pub fn from_nodes(nodes: Vec<Node>) -> Self {
+		Self { nodes }
+	}
+
+	pub fn nodes(&self) -> &[Node] {
+		&self.nodes
+	}
+
+	/// Direct access to the nodes for anything the helpers don't cover, such as adding labels.
+	pub fn nodes_mut(&mut self) -> &mut Vec<Node> {
+		&mut self.nodes
+	}
+
+	pub fn instructions(&self) -> impl Iterator<Item = &Instruction> {
+		self.nodes.iter().filter_map(|node| match node {
+			Node::Instruction(ins, _) => Some(ins),
+			_ => None,
+		})
+	}
+
+	/// The number of instructions.
+	pub fn len(&self) -> usize {
+		self.instructions().count()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/// Index of the first instruction matching `pred`.
+	pub fn position<F: FnMut(&Instruction) -> bool>(&self, pred: F) -> Option<usize> {
+		self.instructions().position(pred)
+	}
+
+	fn node_index(&self, index: usize) -> Option<usize> {
+		self.nodes
+			.iter()
+			.enumerate()
+			.filter(|(_, node)| matches!(node, Node::Instruction(..)))
+			.nth(index)
+			.map(|(idx, _)| idx)
+	}
+
+	/// Inserts an instruction before the instruction at `index`, or at the end if `index` is [len](#method.len).
+	///
+	/// Anything that jumped to the old instruction will now land on the new one.
+	pub fn insert(&mut self, index: usize, ins: Instruction) -> Result<(), PatchError> {
+		let node_index = if index == self.len() {
+			self.nodes.len()
+		} else {
+			self.node_index(index)
+				.ok_or(PatchError::InvalidIndex(index))?
+		};
+
+		self.nodes.insert(node_index, Node::Instruction(ins, ()));
+		Ok(())
+	}
+
+	/// Replaces the instruction at `index`, returning the old one.
+	pub fn replace(&mut self, index: usize, ins: Instruction) -> Result<Instruction, PatchError> {
+		let node_index = self
+			.node_index(index)
+			.ok_or(PatchError::InvalidIndex(index))?;
+
+		match std::mem::replace(&mut self.nodes[node_index], Node::Instruction(ins, ())) {
+			Node::Instruction(old, _) => Ok(old),
+			_ => unreachable!(),
+		}
+	}
+
+	/// Removes the instruction at `index`. Anything that jumped to it will now land on the instruction after it.
+	pub fn remove(&mut self, index: usize) -> Result<Instruction, PatchError> {
+		let node_index = self
+			.node_index(index)
+			.ok_or(PatchError::InvalidIndex(index))?;
+
+		match self.nodes.remove(node_index) {
+			Node::Instruction(old, _) => Ok(old),
+			_ => unreachable!(),
+		}
+	}
+
+	/// Inserts a [custom opcode](custom_opcodes/index.html) that takes `args` arguments before the instruction at
+	/// `index`.
+	///
+	/// It shows up as the global proc call it's assembled from in [instructions](#method.instructions).
+	pub fn insert_custom(
+		&mut self,
+		index: usize,
+		opcode: CustomOpcode,
+		args: u32,
+	) -> Result<(), PatchError> {
+		self.insert(
+			index,
+			Instruction::CallGlob(args, dmasm::operands::Proc(opcode.path())),
+		)
+	}
+
+	pub fn assemble(&self) -> Result<Vec<u32>, PatchError> {
+		self.assemble_with_customs().map(|(bytecode, _)| bytecode)
+	}
+
+	// Also returns what custom_opcodes::record_installed needs to know about the custom opcodes in the result
+	fn assemble_with_customs(&self) -> Result<(Vec<u32>, Vec<(u32, u32)>), PatchError> {
+		let mut bytecode = dmasm::assembler::assemble(&self.nodes, &mut AssembleEnv)
+			.map_err(|e| PatchError::Assembly(format!("{:?}", e)))?;
+
+		let customs = custom_opcodes::substitute(&mut bytecode).map_err(PatchError::Disassembly)?;
+		Ok((bytecode, customs))
+	}
+
+	/// Assembles the patch and makes `proc` run it. [Proc::reset_bytecode](struct.Proc.html#method.reset_bytecode) undoes this.
+	///
+	/// Instruction hooks (and so breakpoints) on the proc are removed, because their offsets don't carry over to the patch.
+	pub fn install(&self, proc: &Proc) -> Result<(), PatchError> {
+		let (bytecode, customs) = self.assemble_with_customs()?;
+		proc.set_bytecode(bytecode);
+
+		// Remembered by address so the proc can still be disassembled
+		custom_opcodes::record_installed(unsafe { proc.bytecode() }, customs);
+		Ok(())
+	}
+}
+
+impl Proc {
+	/// Disassembles this proc so it can be patched. Shorthand for [BytecodePatch::new](struct.BytecodePatch.html#method.new).
+	pub fn patch(&self) -> Result<BytecodePatch, PatchError> {
+		BytecodePatch::new(self)
+	}
+}
@@ -0,0 +1,244 @@
+//! A static call graph of every proc in the world, built by disassembling them.
+//!
+//! Procs are identified by path, so overrides of the same proc share a node, and a `..()` that calls an earlier
+//! override on the same type shows up as the proc calling itself. Calls on a datum only have the name of
+//! the proc to go on, so they're linked to every proc with that name. That makes the graph an over-estimate, which
+//! is the safe direction for finding dead code or working out what might sleep.
+//!
+//! Dynamic calls (`call()`, `call(ref, name)` with a computed name, etc.) can't be seen at all.
+
+use crate::proc::{parent_type, proc_name, proc_type, strip_path};
+use crate::*;
+use dmasm::operands::Variable;
+use dmasm::{Instruction, Node};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CallKind {
+	/// A call to a global proc, like `do_explode()`.
+	Global,
+	/// A call to a proc on a datum, like `src.Life()`.
+	Datum,
+	/// A call to the parent proc with `..()`.
+	Parent,
+}
+
+impl CallKind {
+	fn dot_style(&self) -> &'static str {
+		match self {
+			Self::Global => "solid",
+			Self::Datum => "dashed",
+			Self::Parent => "dotted",
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Call {
+	pub path: String,
+	pub kind: CallKind,
+}
+
+#[derive(Default, Serialize)]
+struct ProcCalls {
+	callers: BTreeSet<Call>,
+	callees: BTreeSet<Call>,
+}
+
+#[derive(Default)]
+pub struct CallGraph {
+	procs: BTreeMap<String, ProcCalls>,
+}
+
+// The name of the proc a datum call refers to
+fn called_name(var: &Variable) -> Option<&[u8]> {
+	match var {
+		Variable::StaticProc(name)
+		| Variable::DynamicProc(name)
+		| Variable::StaticVerb(name)
+		| Variable::DynamicVerb(name) => Some(&name.0),
+		Variable::SetCache(_, rhs) => called_name(rhs),
+		_ => None,
+	}
+}
+
+impl CallGraph {
+	/// Disassembles every proc in the world. This can take a while on big codebases.
+	pub fn build() -> Self {
+		let procs = Proc::all();
+		let mut graph = Self::default();
+
+		let mut by_name: HashMap<&str, BTreeSet<&str>> = HashMap::new();
+		for proc in &procs {
+			graph.procs.entry(proc.path.clone()).or_default();
+			by_name
+				.entry(proc_name(&proc.path))
+				.or_default()
+				.insert(&proc.path);
+		}
+
+		let mut calls = vec![];
+
+		for proc in &procs {
+			let bytecode = instruction_hooks::unhooked_bytecode(proc);
+
+			let mut env = DisassembleEnv;
+			let (nodes, _error) = dmasm::disassembler::disassemble(&bytecode, &mut env);
+
+			for node in nodes {
+				let ins = match node {
+					Node::Instruction(ins, _) => ins,
+					_ => continue,
+				};
+
+				match ins {
+					Instruction::CallGlob(_, callee) => {
+						// Our paths never have /proc/ in them
+						calls.push((proc.path.clone(), strip_path(callee.0), CallKind::Global));
+					}
+
+					Instruction::Call(var, _) => {
+						let name = match called_name(&var) {
+							Some(name) => String::from_utf8_lossy(name).into_owned(),
+							None => continue,
+						};
+
+						for callee in by_name.get(name.as_str()).into_iter().flatten() {
+							calls.push((proc.path.clone(), (*callee).to_owned(), CallKind::Datum));
+						}
+					}
+
+					Instruction::CallParent | Instruction::CallParentArgList => {
+						if let Some(parent) = Self::parent_of(proc) {
+							calls.push((proc.path.clone(), parent, CallKind::Parent));
+						}
+					}
+
+					_ => {}
+				}
+			}
+		}
+
+		for (caller, callee, kind) in calls {
+			graph.add(caller, callee, kind);
+		}
+
+		graph
+	}
+
+	// The proc `..()` calls: the previous override on the same type, or the closest definition on a parent type
+	fn parent_of(proc: &Proc) -> Option<String> {
+		// Overrides share a node, so this is a call to itself
+		if proc.override_id() > 0 {
+			return Some(proc.path.clone());
+		}
+
+		let stripped = strip_path(proc.path.clone());
+		let name = proc_name(&stripped);
+		let mut current = parent_type(proc_type(&stripped));
+
+		while let Some(type_path) = current {
+			if let Some(proc) = Proc::find(format!("{}/{}", type_path, name)) {
+				return Some(proc.path);
+			}
+
+			current = parent_type(type_path);
+		}
+
+		None
+	}
+
+	fn add(&mut self, caller: String, callee: String, kind: CallKind) {
+		self.procs
+			.entry(caller.clone())
+			.or_default()
+			.callees
+			.insert(Call {
+				path: callee.clone(),
+				kind,
+			});
+
+		self.procs
+			.entry(callee)
+			.or_default()
+			.callers
+			.insert(Call { path: caller, kind });
+	}
+
+	/// Every proc in the graph, sorted by path.
+	pub fn procs(&self) -> impl Iterator<Item = &str> {
+		self.procs.keys().map(|x| x.as_str())
+	}
+
+	/// The procs `path` calls.
+	pub fn callees(&self, path: &str) -> Vec<&Call> {
+		match self.procs.get(path) {
+			Some(calls) => calls.callees.iter().collect(),
+			None => vec![],
+		}
+	}
+
+	/// The procs that call `path`.
+	pub fn callers(&self, path: &str) -> Vec<&Call> {
+		match self.procs.get(path) {
+			Some(calls) => calls.callers.iter().collect(),
+			None => vec![],
+		}
+	}
+
+	/// Every proc that can end up calling `path`, directly or not. Handy for finding everything that might sleep.
+	pub fn transitive_callers(&self, path: &str) -> BTreeSet<&str> {
+		let mut seen = BTreeSet::new();
+		let mut pending = vec![path];
+
+		while let Some(path) = pending.pop() {
+			for call in self.callers(path) {
+				if seen.insert(call.path.as_str()) {
+					pending.push(&call.path);
+				}
+			}
+		}
+
+		seen
+	}
+
+	/// Procs that nothing calls. Verbs and procs BYOND calls itself (like `New`) show up here too.
+	pub fn uncalled(&self) -> Vec<&str> {
+		self.procs
+			.iter()
+			.filter(|(_, calls)| calls.callers.is_empty())
+			.map(|(path, _)| path.as_str())
+			.collect()
+	}
+
+	/// The graph in Graphviz's format. Global calls are solid, datum calls are dashed and parent calls are dotted.
+	pub fn to_dot(&self) -> String {
+		let mut dot = String::from("digraph calls {\n");
+
+		for (path, calls) in &self.procs {
+			writeln!(dot, "\t{:?};", path).unwrap();
+
+			for call in &calls.callees {
+				writeln!(
+					dot,
+					"\t{:?} -> {:?} [style={}];",
+					path,
+					call.path,
+					call.kind.dot_style()
+				)
+				.unwrap();
+			}
+		}
+
+		dot.push_str("}\n");
+		dot
+	}
+
+	/// The graph as a JSON object of proc paths to their `callers` and `callees`.
+	pub fn to_json(&self) -> String {
+		serde_json::to_string_pretty(&self.procs).unwrap()
+	}
+}
@@ -0,0 +1,536 @@
+//
+// Compiles DM proc bodies at runtime so broken procs can be replaced without rebuilding the .dmb.
+//
+// dmasm can only compile expressions, so statements are handled here and everything is stitched into one proc:
+// - Each expression is compiled on its own, with every variable the body can see passed to dmasm as a parameter.
+//   dmasm thinks it's compiling the debugger's eval stub, so it follows the expression with code that returns
+//   list(result, ...parameters). That part is the same for every expression, so it's found by compiling `null` and
+//   cut off again.
+// - The parameters are turned back into the real variables: `.`, usr and src, then arguments, then locals.
+// - Every expression numbers its labels from scratch, so they're renamed to keep jumps from colliding.
+// - Statements are the usual DM bytecode around those expressions: pops, returns, and tests and jumps for if/while.
+//
+// The result is installed like any other BytecodePatch, so Proc::reset_bytecode puts the original back.
+//
+
+use crate::*;
+use dmasm::assembler::AssembleEnv as _;
+use dmasm::operands::{Label, Variable};
+use dmasm::{Instruction, Node};
+use std::fmt;
+
+#[derive(Debug)]
+pub struct CompileError {
+	/// 1-based line in the source the error was found on, or None if it isn't about any line in particular.
+	pub line: Option<usize>,
+	pub message: String,
+}
+
+impl fmt::Display for CompileError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.line {
+			Some(line) => write!(f, "line {}: {}", line, self.message),
+			None => write!(f, "{}", self.message),
+		}
+	}
+}
+
+fn error<S: Into<String>>(line: usize, message: S) -> CompileError {
+	CompileError {
+		line: Some(line),
+		message: message.into(),
+	}
+}
+
+fn proc_error<S: Into<String>>(message: S) -> CompileError {
+	CompileError {
+		line: None,
+		message: message.into(),
+	}
+}
+
+// Whether `rest` (what follows a keyword) ends the keyword, rather than carrying on an identifier like `elsewhere`
+fn is_token_boundary(rest: &str) -> bool {
+	match rest.chars().next() {
+		Some(c) => !(c.is_alphanumeric() || c == '_'),
+		None => true,
+	}
+}
+
+fn strip_keyword<'a>(text: &'a str, keyword: &str) -> Option<&'a str> {
+	text.strip_prefix(keyword)
+		.filter(|rest| is_token_boundary(rest))
+}
+
+// Where a `//` comment starts, ignoring any inside string literals
+fn find_comment(line: &str) -> Option<usize> {
+	let bytes = line.as_bytes();
+	let mut quote = None;
+	let mut idx = 0;
+
+	while idx < bytes.len() {
+		match (quote, bytes[idx]) {
+			(Some(_), b'\\') => idx += 1,
+			(Some(q), c) if c == q => quote = None,
+			(None, b'"') | (None, b'\'') => quote = Some(bytes[idx]),
+			(None, b'/') if bytes.get(idx + 1) == Some(&b'/') => return Some(idx),
+			_ => {}
+		}
+
+		idx += 1;
+	}
+
+	None
+}
+
+struct Line<'a> {
+	number: usize,
+	indent: usize,
+	text: &'a str,
+}
+
+fn split_lines(source: &str) -> Vec<Line> {
+	source
+		.lines()
+		.enumerate()
+		.filter_map(|(idx, line)| {
+			let text = match find_comment(line) {
+				Some(comment) => &line[..comment],
+				None => line,
+			};
+
+			let trimmed = text.trim();
+			if trimmed.is_empty() {
+				return None;
+			}
+
+			Some(Line {
+				number: idx + 1,
+				indent: text.len() - text.trim_start().len(),
+				text: trimmed,
+			})
+		})
+		.collect()
+}
+
+// Returns the name declared by a `var/...` statement (the last part of the path) and its initializer
+fn parse_var(text: &str) -> Option<(&str, Option<&str>)> {
+	let rest = text.strip_prefix("var/")?;
+	let (path, init) = match rest.find('=') {
+		Some(idx) => (rest[..idx].trim(), Some(rest[idx + 1..].trim())),
+		None => (rest.trim(), None),
+	};
+
+	let name = path.rsplit('/').next()?;
+	Some((name, init))
+}
+
+// Splits `keyword (condition) trailing` into the condition and any trailing statement
+fn parse_condition<'a>(
+	line: usize,
+	keyword: &str,
+	text: &'a str,
+) -> Result<Option<(&'a str, &'a str)>, CompileError> {
+	let rest = match strip_keyword(text, keyword) {
+		Some(rest) if rest.trim_start().starts_with('(') => rest.trim_start(),
+		_ => return Ok(None),
+	};
+
+	let mut depth = 0;
+	for (idx, c) in rest.char_indices() {
+		match c {
+			'(' => depth += 1,
+			')' => {
+				depth -= 1;
+				if depth == 0 {
+					return Ok(Some((&rest[1..idx], rest[idx + 1..].trim())));
+				}
+			}
+			_ => {}
+		}
+	}
+
+	Err(error(
+		line,
+		format!("unclosed parenthesis after {}", keyword),
+	))
+}
+
+// The variable an instruction reads or writes, for every instruction dmasm's expression compiler uses one in
+fn variable_mut(ins: &mut Instruction) -> Option<&mut Variable> {
+	match ins {
+		Instruction::GetVar(var)
+		| Instruction::SetVar(var)
+		| Instruction::Call(var, _)
+		| Instruction::AugAdd(var)
+		| Instruction::AugSub(var)
+		| Instruction::AugMul(var)
+		| Instruction::AugDiv(var)
+		| Instruction::AugMod(var)
+		| Instruction::AugBand(var)
+		| Instruction::AugBor(var)
+		| Instruction::AugXor(var)
+		| Instruction::AugLShift(var)
+		| Instruction::AugRShift(var)
+		| Instruction::PreInc(var)
+		| Instruction::PreDec(var)
+		| Instruction::PostInc(var)
+		| Instruction::PostDec(var) => Some(var),
+		_ => None,
+	}
+}
+
+// Every label gets renamed, so a jump missing from here points at a label that doesn't exist any more and fails to
+// assemble, rather than jumping somewhere else
+fn jump_target_mut(ins: &mut Instruction) -> Option<&mut Label> {
+	match ins {
+		Instruction::Jmp(label)
+		| Instruction::Jz(label)
+		| Instruction::Jnz(label)
+		| Instruction::JmpOr(label)
+		| Instruction::JmpAnd(label) => Some(label),
+		_ => None,
+	}
+}
+
+// dmasm's parameter `idx` is `variables[idx]`
+fn map_variable(var: &mut Variable, variables: &[Variable]) -> Result<(), String> {
+	match var {
+		Variable::Arg(idx) => {
+			let idx = *idx as usize;
+			*var = variables
+				.get(idx)
+				.cloned()
+				.ok_or_else(|| format!("dmasm used unknown parameter {}", idx))?;
+		}
+
+		Variable::SetCache(lhs, rhs) => {
+			map_variable(lhs, variables)?;
+			map_variable(rhs, variables)?;
+		}
+
+		_ => {}
+	}
+
+	Ok(())
+}
+
+struct Compiler<'a> {
+	lines: Vec<Line<'a>>,
+	pos: usize,
+	// What dmasm calls each variable, and what it really is
+	names: Vec<String>,
+	variables: Vec<Variable>,
+	// What dmasm puts after every expression
+	epilogue: Vec<Node>,
+	nodes: Vec<Node>,
+	labels: usize,
+	expressions: usize,
+}
+
+impl<'a> Compiler<'a> {
+	fn push(&mut self, ins: Instruction) {
+		self.nodes.push(Node::Instruction(ins, ()));
+	}
+
+	fn label(&mut self) -> String {
+		self.labels += 1;
+		format!("COMPILED_{}", self.labels)
+	}
+
+	// Leaves the expression's value on the stack
+	fn expression(&mut self, line: usize, code: &str) -> Result<(), CompileError> {
+		let names: Vec<&str> = self.names.iter().map(|x| x.as_str()).collect();
+
+		let mut nodes = dmasm::compiler::compile_expr(code, &names)
+			.map_err(|e| error(line, format!("{}", e)))?;
+
+		if nodes.len() <= self.epilogue.len() || !nodes.ends_with(&self.epilogue) {
+			return Err(error(
+				line,
+				format!("dmasm compiled {:?} into something unexpected", code),
+			));
+		}
+
+		nodes.truncate(nodes.len() - self.epilogue.len());
+
+		self.expressions += 1;
+		let prefix = format!("EXPR_{}_", self.expressions);
+
+		for node in nodes {
+			match node {
+				Node::Label(name) => self.nodes.push(Node::Label(format!("{}{}", prefix, name))),
+
+				Node::Instruction(mut ins, _) => {
+					if let Some(label) = jump_target_mut(&mut ins) {
+						label.0 = format!("{}{}", prefix, label.0);
+					}
+
+					if let Some(var) = variable_mut(&mut ins) {
+						map_variable(var, &self.variables).map_err(|e| error(line, e))?;
+					}
+
+					self.push(ins);
+				}
+
+				Node::Comment(_) => {}
+			}
+		}
+
+		Ok(())
+	}
+
+	// Jumps to `otherwise` if the condition is false
+	fn condition(&mut self, line: usize, code: &str, otherwise: &str) -> Result<(), CompileError> {
+		self.expression(line, code)?;
+		self.push(Instruction::Test);
+		self.push(Instruction::Jz(Label(otherwise.to_owned())));
+		Ok(())
+	}
+
+	// One arm of an if, which jumps to `end` once its body is done
+	fn branch(
+		&mut self,
+		line: usize,
+		indent: usize,
+		condition: &str,
+		trailing: &str,
+		end: &str,
+	) -> Result<(), CompileError> {
+		let next = self.label();
+		self.condition(line, condition, &next)?;
+		self.body(line, indent, trailing)?;
+		self.push(Instruction::Jmp(Label(end.to_owned())));
+		self.nodes.push(Node::Label(next));
+		Ok(())
+	}
+
+	// Compiles a statement that has no block of its own
+	fn simple_statement(&mut self, line: usize, text: &str) -> Result<(), CompileError> {
+		// Like DM, a declaration without a value sets the local to null every time it runs
+		if let Some((name, init)) = parse_var(text) {
+			let init = init.unwrap_or("null");
+			self.expression(line, &format!("{} = ({})", name, init))?;
+			self.push(Instruction::Pop);
+			return Ok(());
+		}
+
+		if text == "return" {
+			self.push(Instruction::End);
+			return Ok(());
+		}
+
+		if let Some(expr) = text.strip_prefix("return ") {
+			self.expression(line, expr)?;
+			self.push(Instruction::Ret);
+			return Ok(());
+		}
+
+		self.expression(line, text)?;
+		self.push(Instruction::Pop);
+		Ok(())
+	}
+
+	// The body of an if/else/while: either the rest of the line, or the indented lines below it
+	fn body(&mut self, line: usize, indent: usize, trailing: &str) -> Result<(), CompileError> {
+		if !trailing.is_empty() {
+			return self.simple_statement(line, trailing);
+		}
+
+		match self.lines.get(self.pos) {
+			Some(next) if next.indent > indent => {
+				let indent = next.indent;
+				self.block(indent)
+			}
+			_ => Err(error(line, "expected an indented block")),
+		}
+	}
+
+	fn block(&mut self, indent: usize) -> Result<(), CompileError> {
+		while let Some(line) = self.lines.get(self.pos) {
+			if line.indent < indent {
+				break;
+			}
+
+			if line.indent > indent {
+				return Err(error(line.number, "unexpected indentation"));
+			}
+
+			let (number, text) = (line.number, line.text);
+			self.pos += 1;
+
+			if let Some((condition, trailing)) = parse_condition(number, "while", text)? {
+				let start = self.label();
+				let end = self.label();
+
+				self.nodes.push(Node::Label(start.clone()));
+				self.condition(number, condition, &end)?;
+				self.body(number, indent, trailing)?;
+				self.push(Instruction::Jmp(Label(start)));
+				self.nodes.push(Node::Label(end));
+				continue;
+			}
+
+			if let Some((condition, trailing)) = parse_condition(number, "if", text)? {
+				let end = self.label();
+				self.branch(number, indent, condition, trailing, &end)?;
+
+				while let Some(line) = self.lines.get(self.pos) {
+					if line.indent != indent {
+						break;
+					}
+
+					let (number, text) = (line.number, line.text);
+					let rest = match strip_keyword(text, "else") {
+						Some(rest) => rest.trim(),
+						None => break,
+					};
+
+					self.pos += 1;
+
+					if let Some((condition, trailing)) = parse_condition(number, "if", rest)? {
+						self.branch(number, indent, condition, trailing, &end)?;
+						continue;
+					}
+
+					self.body(number, indent, rest)?;
+					break;
+				}
+
+				self.nodes.push(Node::Label(end));
+				continue;
+			}
+
+			if strip_keyword(text, "else").is_some() {
+				return Err(error(number, "else without a matching if"));
+			}
+
+			self.simple_statement(number, text)?;
+		}
+
+		Ok(())
+	}
+}
+
+impl Proc {
+	/// Compiles DM code and installs it as this proc's bytecode. Replaces any earlier compiled body or patch.
+	///
+	/// Supports `var/` declarations, `if`/`else if`/`else`, `while`, `return` and expression statements.
+	/// Blocks are marked by indentation, like in DM. The proc's arguments, `src`, `usr` and `.` are all available.
+	/// The result is ordinary bytecode, so it can sleep, and [reset_bytecode](#method.reset_bytecode) restores the
+	/// original.
+	///
+	/// Locals the proc doesn't already have are added to it. BYOND names them with entries in its table of every
+	/// variable name in the world, so a new local has to be named the same as some variable that already exists.
+	///
+	/// Fails if the proc is hooked from Rust, because its bytecode would never run.
+	///
+	/// # Examples
+	/// ```ignore
+	/// let proc = Proc::find("/mob/proc/take_damage").unwrap();
+	/// proc.compile_body("
+	/// var/total = amount * damage_multiplier
+	/// if (total <= 0)
+	/// 	return 0
+	/// health -= total
+	/// return total
+	/// ")?;
+	/// ```
+	pub fn compile_body<S: AsRef<str>>(&self, source: S) -> Result<(), CompileError> {
+		if hooks::is_hooked(self.id) {
+			return Err(proc_error(format!(
+				"{} is hooked, so its bytecode never runs",
+				self.path
+			)));
+		}
+
+		let lines = split_lines(source.as_ref());
+
+		let mut names: Vec<String> = vec![".".into(), "usr".into(), "src".into()];
+		let mut variables = vec![Variable::Dot, Variable::Usr, Variable::Src];
+
+		for (idx, name) in self.parameter_names().iter().enumerate() {
+			names.push(String::from(name));
+			variables.push(Variable::Arg(idx as u32));
+		}
+
+		// Locals are proc-wide so every expression can see all of them. Ones the proc already has keep their slot.
+		let existing: Vec<String> = self.local_names().iter().map(String::from).collect();
+		let mut added = vec![];
+
+		for line in &lines {
+			if let Some((name, _)) = parse_var(line.text) {
+				if names.iter().any(|x| x == name) {
+					return Err(error(
+						line.number,
+						format!("duplicate definition of {}", name),
+					));
+				}
+
+				let slot = match existing.iter().position(|x| x == name) {
+					Some(idx) => idx,
+					None => {
+						let id = AssembleEnv
+							.get_variable_name_index(name.as_bytes())
+							.ok_or_else(|| {
+								error(
+									line.number,
+									format!("no variable in the world is called {}, so it can't be a local", name),
+								)
+							})?;
+
+						added.push(raw_types::strings::VariableId(id));
+						existing.len() + added.len() - 1
+					}
+				};
+
+				names.push(name.to_owned());
+				variables.push(Variable::Local(slot as u32));
+			}
+		}
+
+		let epilogue = {
+			let names: Vec<&str> = names.iter().map(|x| x.as_str()).collect();
+			let mut nodes = dmasm::compiler::compile_expr("null", &names)
+				.map_err(|e| proc_error(format!("{}", e)))?;
+
+			// `null` is a single instruction, and everything after it is the epilogue
+			if nodes.is_empty() {
+				return Err(proc_error("dmasm compiled null into nothing"));
+			}
+			nodes.remove(0);
+			nodes
+		};
+
+		let indent = lines.first().map_or(0, |x| x.indent);
+		let mut compiler = Compiler {
+			lines,
+			pos: 0,
+			names,
+			variables,
+			epilogue,
+			nodes: vec![],
+			labels: 0,
+			expressions: 0,
+		};
+
+		compiler.block(indent)?;
+
+		if let Some(line) = compiler.lines.get(compiler.pos) {
+			return Err(error(line.number, "unexpected indentation"));
+		}
+
+		compiler.push(Instruction::End);
+
+		// The original bytecode doesn't mind having spare locals, so this goes first
+		if !added.is_empty() && bytecode_manager::add_locals(self, &added).is_none() {
+			return Err(proc_error(format!("{} has too many locals", self.path)));
+		}
+
+		BytecodePatch::from_nodes(compiler.nodes)
+			.install(self)
+			.map_err(|e| proc_error(format!("{}", e)))?;
+
+		Ok(())
+	}
+}
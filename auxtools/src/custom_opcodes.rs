@@ -0,0 +1,364 @@
+//! Opcodes that BYOND doesn't have, handled by Rust.
+//!
+//! A custom opcode looks like a call to a global proc named after it, so `square(x)` compiles to the opcode
+//! registered as `square`. Its handler finds the arguments on the operand stack and replaces them with its result,
+//! which is much cheaper than calling a hooked proc, so it's useful for tiny helpers in hot code.
+//!
+//! [AssembleEnv](../struct.AssembleEnv.html) resolves `/proc/<name>` to a registered opcode before any real proc of
+//! that name, so the opcode can be used from hand-written assembly, [BytecodePatch](../struct.BytecodePatch.html)es
+//! and [Proc::compile_body](../struct.Proc.html#method.compile_body) alike. Once assembled, the call's opcode is
+//! swapped for the custom one. [instruction_hooks::unhooked_bytecode](../instruction_hooks/fn.unhooked_bytecode.html)
+//! swaps it back, so the disassembler (and everything built on it) sees the call, and patching the proc again keeps
+//! the opcode. Custom opcodes can't be hooked, so the debugger can't put a breakpoint on one.
+//!
+//! # Examples
+//! ```ignore
+//! fn square(stack: &mut OpcodeStack) -> DMResult<()> {
+//!     let x = stack.pop()?.as_number()?;
+//!     stack.push(Value::from(x * x))
+//! }
+//!
+//! #[init(partial)]
+//! fn setup() -> Result<(), String> {
+//!     custom_opcodes::register("square", square).map_err(|e| e.to_string())?;
+//!
+//!     let proc = Proc::find("/mob/proc/get_speed").unwrap();
+//!     proc.compile_body("return square(speed) + 1").map_err(|e| e.to_string())
+//! }
+//! ```
+
+use crate::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+// Well clear of both BYOND's opcodes and our instruction hooks
+const FIRST_OPCODE: u32 = 0x1400;
+const MAX_OPCODES: u32 = 0x100;
+
+// The proc ids AssembleEnv gives custom opcodes, one per opcode. Real proc ids never get this high.
+const FIRST_PROC_ID: u32 = 0xFFFF_FF00;
+
+/// Handles a custom opcode. It should pop its arguments and push its result. If it doesn't, extra values are
+/// dropped from the top of the stack and a missing result is null.
+///
+/// If it returns an error, the error is reported as a runtime at the opcode's location and the proc carries on
+/// with the next instruction.
+pub type OpcodeHandler = fn(&mut OpcodeStack) -> DMResult<()>;
+
+/// A registered custom opcode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CustomOpcode(u32);
+
+impl CustomOpcode {
+	/// The value this opcode is encoded as in bytecode.
+	pub fn opcode(&self) -> u32 {
+		self.0
+	}
+
+	pub fn name(&self) -> String {
+		OPCODES.with(|x| x.borrow()[(self.0 - FIRST_OPCODE) as usize].0.clone())
+	}
+
+	/// The global proc calls to this opcode are written as.
+	pub fn path(&self) -> String {
+		format!("/proc/{}", self.name())
+	}
+
+	// The proc id AssembleEnv stands in for this opcode with
+	fn proc_id(&self) -> u32 {
+		FIRST_PROC_ID + (self.0 - FIRST_OPCODE)
+	}
+}
+
+#[derive(Debug)]
+pub enum RegisterOpcodeError {
+	/// Every custom opcode is in use.
+	TooMany,
+	/// Couldn't install the instruction handler.
+	Unavailable(instruction_hooks::InstructionHookError),
+}
+
+impl std::fmt::Display for RegisterOpcodeError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::TooMany => write!(
+				f,
+				"no more than {} custom opcodes can be registered",
+				MAX_OPCODES
+			),
+			Self::Unavailable(e) => write!(f, "{}", e),
+		}
+	}
+}
+
+thread_local! {
+	// Indexed by opcode - FIRST_OPCODE. Never cleared, so opcodes keep their values between reloads.
+	static OPCODES: RefCell<Vec<(String, OpcodeHandler)>> = RefCell::new(vec![]);
+
+	// The offset of every custom opcode in installed bytecode and the call opcode it replaced, keyed by the
+	// bytecode's address. The bytecode manager keeps that bytecode alive until shutdown.
+	static INSTALLED: RefCell<HashMap<usize, Vec<(u32, u32)>>> = RefCell::new(HashMap::new());
+}
+
+/// The proc id [AssembleEnv](../struct.AssembleEnv.html) should use for a call to `path`, if it's a custom opcode.
+pub(crate) fn proc_id(path: &str) -> Option<u32> {
+	let name = path.strip_prefix("/proc/").unwrap_or(path);
+	find(name).map(|x| x.proc_id())
+}
+
+/// The path of the custom opcode standing in for proc `id`, for [DisassembleEnv](../struct.DisassembleEnv.html).
+pub(crate) fn proc_path(id: u32) -> Option<String> {
+	from_opcode(id.checked_sub(FIRST_PROC_ID)? + FIRST_OPCODE).map(|x| x.path())
+}
+
+/// Swaps the calls to custom opcodes in freshly assembled bytecode for the opcodes themselves. Returns what
+/// [record_installed] needs to undo that for the disassembler.
+pub(crate) fn substitute(bytecode: &mut [u32]) -> Result<Vec<(u32, u32)>, String> {
+	if OPCODES.with(|x| x.borrow().is_empty()) {
+		return Ok(vec![]);
+	}
+
+	let (nodes, error) = dmasm::disassembler::disassemble(bytecode, &mut DisassembleEnv);
+
+	if let Some(error) = error {
+		return Err(format!("{:?}", error));
+	}
+
+	let mut res = vec![];
+	for node in nodes {
+		let offset = match node {
+			dmasm::Node::Instruction(dmasm::Instruction::CallGlob(..), debug) => debug.offset,
+			_ => continue,
+		};
+
+		// The call's operands are its argument count and the proc id
+		let id = bytecode[offset as usize + 2];
+		if let Some(opcode) = id.checked_sub(FIRST_PROC_ID) {
+			res.push((offset, bytecode[offset as usize]));
+			bytecode[offset as usize] = FIRST_OPCODE + opcode;
+		}
+	}
+
+	Ok(res)
+}
+
+pub(crate) fn record_installed(bytecode: &[u32], opcodes: Vec<(u32, u32)>) {
+	INSTALLED.with(|x| {
+		let mut installed = x.borrow_mut();
+		if opcodes.is_empty() {
+			installed.remove(&(bytecode.as_ptr() as usize));
+		} else {
+			installed.insert(bytecode.as_ptr() as usize, opcodes);
+		}
+	});
+}
+
+// The offset of every custom opcode in `bytecode` and the call it replaced, if it was installed by a patch
+fn installed(bytecode: &[u32]) -> Vec<(u32, u32)> {
+	INSTALLED.with(|x| {
+		x.borrow()
+			.get(&(bytecode.as_ptr() as usize))
+			.cloned()
+			.unwrap_or_default()
+	})
+}
+
+/// Turns every custom opcode in `dst`, a copy of `bytecode`, back into the call it was assembled from.
+pub(crate) fn mask(bytecode: &[u32], dst: &mut [u32]) {
+	for (offset, call) in installed(bytecode) {
+		dst[offset as usize] = call;
+	}
+}
+
+pub(crate) fn is_custom(opcode: u32) -> bool {
+	(FIRST_OPCODE..FIRST_OPCODE + MAX_OPCODES).contains(&opcode)
+}
+
+pub(crate) fn shutdown() {
+	INSTALLED.with(|x| x.borrow_mut().clear());
+}
+
+/// Registers a custom opcode, or replaces the handler of the one with the same name.
+pub fn register(name: &str, handler: OpcodeHandler) -> Result<CustomOpcode, RegisterOpcodeError> {
+	instruction_hooks::enable().map_err(RegisterOpcodeError::Unavailable)?;
+
+	OPCODES.with(|opcodes| {
+		let mut opcodes = opcodes.borrow_mut();
+
+		if let Some(idx) = opcodes.iter().position(|(x, _)| x == name) {
+			opcodes[idx].1 = handler;
+			return Ok(CustomOpcode(FIRST_OPCODE + idx as u32));
+		}
+
+		if opcodes.len() as u32 >= MAX_OPCODES {
+			return Err(RegisterOpcodeError::TooMany);
+		}
+
+		opcodes.push((name.to_owned(), handler));
+		Ok(CustomOpcode(FIRST_OPCODE + opcodes.len() as u32 - 1))
+	})
+}
+
+/// Finds a custom opcode by the name it was registered with.
+pub fn find(name: &str) -> Option<CustomOpcode> {
+	OPCODES.with(|opcodes| {
+		opcodes
+			.borrow()
+			.iter()
+			.position(|(x, _)| x == name)
+			.map(|idx| CustomOpcode(FIRST_OPCODE + idx as u32))
+	})
+}
+
+/// Looks up the custom opcode bytecode is encoded with.
+pub fn from_opcode(opcode: u32) -> Option<CustomOpcode> {
+	let idx = opcode.checked_sub(FIRST_OPCODE)?;
+	OPCODES.with(|opcodes| {
+		if (idx as usize) < opcodes.borrow().len() {
+			Some(CustomOpcode(opcode))
+		} else {
+			None
+		}
+	})
+}
+
+/// The operand stack of the proc running a custom opcode.
+///
+/// BYOND sizes the stack when compiling the proc and we can't ask it how big that is, so [push](#method.push) only
+/// fills the slots the call the opcode was assembled from would have used: its arguments' and its result's. Use
+/// [push_unchecked](#method.push_unchecked) if you know there's room for more. [pop](#method.pop) stops at the
+/// arguments, because everything under them belongs to the rest of the proc.
+pub struct OpcodeStack {
+	ctx: *mut raw_types::procs::ExecutionContext,
+	args: usize,
+	// What pop and push can't go below and above
+	floor: usize,
+	ceiling: usize,
+}
+
+impl OpcodeStack {
+	/// The context executing the opcode.
+	pub fn context(&self) -> *mut raw_types::procs::ExecutionContext {
+		self.ctx
+	}
+
+	/// How many arguments the opcode was called with.
+	pub fn args(&self) -> usize {
+		self.args
+	}
+
+	pub fn len(&self) -> usize {
+		unsafe { (*self.ctx).stack_size as usize }
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	pub fn pop(&mut self) -> DMResult {
+		unsafe {
+			if (*self.ctx).stack_size as usize <= self.floor {
+				return Err(runtime!("custom opcode popped more than its arguments"));
+			}
+
+			(*self.ctx).stack_size -= 1;
+
+			// The stack's reference is ours now
+			Ok(Value::from_raw_owned(
+				*(*self.ctx).stack.add((*self.ctx).stack_size as usize),
+			))
+		}
+	}
+
+	/// Pushes a value. Fails if the stack would grow past what the call the opcode replaced could have used.
+	pub fn push(&mut self, value: Value) -> DMResult<()> {
+		if self.len() >= self.ceiling {
+			return Err(runtime!("custom opcode pushed past the end of its stack"));
+		}
+
+		unsafe {
+			self.push_unchecked(value);
+		}
+		Ok(())
+	}
+
+	/// Pushes a value without checking that the stack has room for it.
+	///
+	/// # Safety
+	/// The proc's stack has to have a free slot. BYOND only allocates as many as the proc was compiled to need, so
+	/// this is only safe when the patch that inserted the opcode also made room for it.
+	pub unsafe fn push_unchecked(&mut self, value: Value) {
+		*(*self.ctx).stack.add((*self.ctx).stack_size as usize) = value.raw;
+		(*self.ctx).stack_size += 1;
+
+		// Stealing our reference out of the Value
+		std::mem::forget(value);
+	}
+
+	/// Returns the value `depth` places from the top of the stack without removing it.
+	pub fn peek(&self, depth: usize) -> Option<Value> {
+		unsafe {
+			let len = (*self.ctx).stack_size as usize;
+			if depth >= len {
+				return None;
+			}
+
+			Some(Value::from_raw(*(*self.ctx).stack.add(len - depth - 1)))
+		}
+	}
+}
+
+// Runs any custom opcodes at the context's current offset, leaving it on the next real instruction
+pub(crate) fn dispatch(ctx: *mut raw_types::procs::ExecutionContext) {
+	loop {
+		let (opcode, args) = unsafe {
+			let offset = (*ctx).bytecode_offset as usize;
+			(
+				*(*ctx).bytecode.add(offset),
+				*(*ctx).bytecode.add(offset + 1),
+			)
+		};
+
+		// Checked first so ordinary instructions don't pay for the lookup
+		if !is_custom(opcode) {
+			return;
+		}
+
+		let handler = match OPCODES.with(|x| {
+			x.borrow()
+				.get((opcode - FIRST_OPCODE) as usize)
+				.map(|x| x.1)
+		}) {
+			Some(handler) => handler,
+			None => return,
+		};
+
+		let args = args as usize;
+		let start = unsafe { (*ctx).stack_size as usize };
+		let mut stack = OpcodeStack {
+			ctx,
+			args,
+			floor: start.saturating_sub(args),
+			// The call would have pushed its result over its arguments
+			ceiling: start.max(start.saturating_sub(args) + 1),
+		};
+
+		if let Err(e) = handler(&mut stack) {
+			crate::runtime::report_runtime(&unsafe { e.with_context(ctx) });
+		}
+
+		// Leave the stack how the call would have
+		let result = start.saturating_sub(args) + 1;
+		while stack.len() > result {
+			let _ = stack.pop();
+		}
+		if stack.len() < result {
+			let _ = stack.push(Value::null());
+		}
+
+		unsafe {
+			// The opcode, the argument count and the proc id
+			(*ctx).bytecode_offset += 3;
+		}
+	}
+}
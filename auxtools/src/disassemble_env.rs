@@ -1,5 +1,4 @@
-use auxtools::*;
-use dmasm;
+use crate::*;
 
 pub struct DisassembleEnv;
 
@@ -25,6 +24,10 @@ impl dmasm::disassembler::DisassembleEnv for DisassembleEnv {
 	}
 
 	fn get_proc_name(&mut self, index: u32) -> Option<String> {
+		if let Some(path) = custom_opcodes::proc_path(index) {
+			return Some(path);
+		}
+
 		Proc::from_id(raw_types::procs::ProcId(index)).map(|x| x.path)
 	}
 
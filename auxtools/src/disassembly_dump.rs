@@ -0,0 +1,86 @@
+//! Writes the disassembly of every proc in the world to a directory, so two builds can be diffed at the bytecode level.
+//!
+//! The tree mirrors type paths: `/mob/living/proc/Life` goes to `<dir>/mob/living/Life.dmasm` and the global
+//! `/proc/do_stuff` to `<dir>/do_stuff.dmasm`. Overrides on the same type get the override id added, like
+//! `Life.1.dmasm`.
+//!
+//! Procs are always dumped as they were compiled, so hooks, breakpoints and patches don't show up. Nothing in the
+//! output depends on the process (no addresses or proc ids), so the same code dumps to the same files every time.
+
+use crate::proc::proc_name;
+use crate::*;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// What dumping the world did.
+#[derive(Clone, Debug, Default)]
+pub struct DumpReport {
+	/// Procs written out.
+	pub procs: usize,
+	/// Procs that couldn't be fully disassembled and the error. Their files have everything up to the error.
+	pub errors: Vec<(String, String)>,
+}
+
+/// The disassembly of a proc's original bytecode, in the format used by the dump.
+///
+/// Returns the error alongside the text if disassembly stopped early.
+pub fn disassemble_proc(proc: &Proc) -> (String, Option<String>) {
+	let bytecode = instruction_hooks::unhooked_original_bytecode(proc);
+
+	let mut env = DisassembleEnv;
+	let (nodes, error) = dmasm::disassembler::disassemble(&bytecode, &mut env);
+
+	let mut text = format!("; {} (override {})\n", proc.path, proc.override_id());
+	text.push_str(&dmasm::format_disassembly(&nodes, None));
+
+	let error = error.map(|e| format!("{:?}", e));
+	if let Some(error) = &error {
+		text.push_str(&format!("\n; disassembly stopped: {}", error));
+	}
+
+	if !text.ends_with('\n') {
+		text.push('\n');
+	}
+
+	(text, error)
+}
+
+/// Where a proc ends up under `dir`.
+pub fn dump_path(dir: &Path, proc: &Proc) -> PathBuf {
+	let mut path = dir.to_path_buf();
+	path.extend(proc.path.split('/').filter(|x| !x.is_empty()));
+
+	let file_name = match proc.override_id() {
+		0 => format!("{}.dmasm", proc_name(&proc.path)),
+		id => format!("{}.{}.dmasm", proc_name(&proc.path), id),
+	};
+
+	path.set_file_name(file_name);
+	path
+}
+
+/// Writes every proc under `dir`, creating directories as needed. Existing files are overwritten but nothing is
+/// deleted, so dump into an empty directory if the results are going to be diffed.
+pub fn dump_world<P: AsRef<Path>>(dir: P) -> io::Result<DumpReport> {
+	let dir = dir.as_ref();
+	let mut report = DumpReport::default();
+
+	for proc in Proc::all() {
+		let path = dump_path(dir, &proc);
+		let (text, error) = disassemble_proc(&proc);
+
+		if let Some(parent) = path.parent() {
+			fs::create_dir_all(parent)?;
+		}
+		fs::write(&path, text)?;
+
+		report.procs += 1;
+		if let Some(error) = error {
+			report.errors.push((proc.path.clone(), error));
+		}
+	}
+
+	report.errors.sort();
+	Ok(report)
+}
@@ -20,7 +20,15 @@ extern "C" {
 // If the top of this stack is true, we replace byond's runtime exceptions with our own
 std::stack<bool> runtime_contexts({false});
 
-extern "C" void on_runtime(const char* pError);
+extern "C" bool on_runtime(const char* pError);
+extern "C" void end_suppressed_runtime();
+
+// Ends a suppressed runtime whether BYOND returns from it or unwinds through the hook
+struct SuppressedRuntime {
+	~SuppressedRuntime() {
+		end_suppressed_runtime();
+	}
+};
 
 extern "C" void runtime_hook(char* pError) {
 	const char* pErrorCorrected = (pError != nullptr) ? pError : "<null>";
@@ -33,7 +41,12 @@ extern "C" void runtime_hook(char* pError) {
 		return;
 	}
 
-	on_runtime(pErrorCorrected);
+	if (on_runtime(pErrorCorrected)) {
+		// BYOND still has to stop the proc, it just doesn't get to log anything
+		SuppressedRuntime suppressed;
+		return runtime_original(pError);
+	}
+
 	return runtime_original(pError);
 }
 
@@ -1,23 +1,42 @@
 use super::proc::Proc;
 use super::raw_types;
 use super::value::Value;
-use crate::runtime::DMResult;
+use crate::panics;
+use crate::runtime::{DMResult, Runtime};
+use crate::runtime_event::RuntimeEvent;
 use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
 use detour::RawDetour;
 use std::ffi::c_void;
 use std::os::raw::c_char;
+use std::rc::Rc;
 use std::{cell::RefCell, ffi::CStr};
 
 #[doc(hidden)]
 pub struct CompileTimeHook {
 	pub proc_path: &'static str,
 	pub hook: ProcHook,
+	pub fn_name: Option<&'static str>,
 }
 
 impl CompileTimeHook {
+	#[deprecated(
+		note = "use CompileTimeHook::with_name so hook errors can say which function failed"
+	)]
 	pub fn new(proc_path: &'static str, hook: ProcHook) -> Self {
-		CompileTimeHook { proc_path, hook }
+		CompileTimeHook {
+			proc_path,
+			hook,
+			fn_name: None,
+		}
+	}
+
+	pub fn with_name(proc_path: &'static str, hook: ProcHook, fn_name: &'static str) -> Self {
+		CompileTimeHook {
+			proc_path,
+			hook,
+			fn_name: Some(fn_name),
+		}
 	}
 }
 
@@ -25,7 +44,7 @@ inventory::collect!(CompileTimeHook);
 
 // TODO: This is super deceptively named
 #[doc(hidden)]
-pub struct RuntimeHook(pub fn(&str));
+pub struct RuntimeHook(pub fn(&RuntimeEvent));
 inventory::collect!(RuntimeHook);
 
 extern "C" {
@@ -92,17 +111,72 @@ pub fn init() -> Result<(), String> {
 
 pub type ProcHook = fn(&Value, &Value, &mut Vec<Value>) -> DMResult;
 
+#[derive(Clone)]
+struct RegisteredHook {
+	func: ProcHook,
+	// Only known for hooks made with the hook macro
+	fn_name: Option<&'static str>,
+}
+
+/// Everything there is to know about a hook that returned an error (or panicked).
+pub struct HookError<'a> {
+	/// Path of the hooked proc.
+	pub proc: &'a str,
+	/// Name of the Rust function the proc is hooked with, if it's known.
+	pub fn_name: Option<&'static str>,
+	pub src: &'a Value,
+	pub usr: &'a Value,
+	pub runtime: Runtime,
+}
+
+type HookErrorHandler = Rc<dyn Fn(&HookError)>;
+
 thread_local! {
-	static PROC_HOOKS: RefCell<DashMap<raw_types::procs::ProcId, ProcHook>> = RefCell::new(DashMap::new());
+	static PROC_HOOKS: RefCell<DashMap<raw_types::procs::ProcId, RegisteredHook>> = RefCell::new(DashMap::new());
+	static ERROR_HANDLER: RefCell<Option<HookErrorHandler>> = RefCell::new(None);
+}
+
+/// Replaces what happens when a hook returns an error or panics. The hooked proc returns null either way.
+///
+/// Handlers are reset on shutdown, so set them up in an [init](attr.init.html) function.
+///
+/// # Examples
+/// ```ignore
+/// #[init(partial)]
+/// fn setup_errors() -> Result<(), String> {
+///     set_hook_error_handler(|e| {
+///         log::error!("{} failed in {}: {}", e.fn_name.unwrap_or("?"), e.proc, e.runtime);
+///         default_hook_error_handler(e);
+///     });
+///     Ok(())
+/// }
+/// ```
+pub fn set_hook_error_handler<F: Fn(&HookError) + 'static>(handler: F) {
+	ERROR_HANDLER.with(|x| x.replace(Some(Rc::new(handler))));
+}
+
+/// Goes back to [default_hook_error_handler](fn.default_hook_error_handler.html).
+pub fn reset_hook_error_handler() {
+	ERROR_HANDLER.with(|x| x.replace(None));
+}
+
+/// Calls `/proc/auxtools_stack_trace` with the error's message, which is expected to `CRASH()` with it so that
+/// BYOND logs it with a stack trace. If that proc doesn't exist the message goes to stderr instead.
+pub fn default_hook_error_handler(e: &HookError) {
+	crate::runtime::report_runtime_in(&e.runtime, e.fn_name);
 }
 
-fn hook_by_id(id: raw_types::procs::ProcId, hook: ProcHook) -> Result<(), HookFailure> {
+fn hook_by_id(
+	id: raw_types::procs::ProcId,
+	func: ProcHook,
+	fn_name: Option<&'static str>,
+) -> Result<(), HookFailure> {
 	PROC_HOOKS.with(|h| {
 		let map = h.borrow();
 		let entry = map.entry(id);
 		match entry {
 			Entry::Vacant(v) => {
-				v.insert(hook);
+				v.insert(RegisteredHook { func, fn_name });
 				Ok(())
 			}
 			Entry::Occupied(_) => Err(HookFailure::AlreadyHooked),
@@ -110,29 +184,97 @@ fn hook_by_id(id: raw_types::procs::ProcId, hook: ProcHook) -> Result<(), HookFa
 	})
 }
 
+fn unhook_by_id(id: raw_types::procs::ProcId) -> bool {
+	PROC_HOOKS.with(|h| h.borrow().remove(&id).is_some())
+}
+
+pub(crate) fn is_hooked(id: raw_types::procs::ProcId) -> bool {
+	PROC_HOOKS.with(|h| h.borrow().contains_key(&id))
+}
+
 pub fn clear_hooks() {
 	PROC_HOOKS.with(|h| h.borrow().clear());
+	reset_hook_error_handler();
 }
 
-pub fn hook<S: Into<String>>(name: S, hook: ProcHook) -> Result<(), HookFailure> {
+pub fn hook<S: Into<String>>(
+	name: S,
+	hook: ProcHook,
+	fn_name: Option<&'static str>,
+) -> Result<(), HookFailure> {
 	match super::proc::get_proc(name) {
-		Some(p) => hook_by_id(p.id, hook),
+		Some(p) => hook_by_id(p.id, hook, fn_name),
 		None => Err(HookFailure::ProcNotFound),
 	}
 }
 
 impl Proc {
 	pub fn hook(&self, func: ProcHook) -> Result<(), HookFailure> {
-		hook_by_id(self.id, func)
+		hook_by_id(self.id, func, None)
+	}
+
+	/// Removes this proc's hook, if it has one. Returns false if it wasn't hooked.
+	pub fn unhook(&self) -> bool {
+		unhook_by_id(self.id)
 	}
 }
 
+// Returns true if the runtime was suppressed, in which case the C++ hook calls end_suppressed_runtime once BYOND is
+// done with it
 #[no_mangle]
-extern "C" fn on_runtime(error: *const c_char) {
+extern "C" fn on_runtime(error: *const c_char) -> bool {
 	let str = unsafe { CStr::from_ptr(error) }.to_string_lossy();
 
-	for func in inventory::iter::<RuntimeHook> {
-		func.0(&str);
+	let res = panics::catch(|| {
+		crate::runtime::on_dm_runtime(&str);
+		crate::runtime_event::dispatch(&str)
+	});
+
+	match res {
+		Ok(suppressed) => suppressed,
+		Err(e) => {
+			eprintln!("auxtools: runtime handler failed: {}", e);
+			false
+		}
+	}
+}
+
+// Hooks are never part of byond's stack, so the runtime wouldn't mention them without the proc
+fn report_error(
+	proc_id: raw_types::procs::ProcId,
+	fn_name: Option<&'static str>,
+	src: &Value,
+	usr: &Value,
+	mut runtime: Runtime,
+) {
+	let proc = Proc::from_id(proc_id)
+		.map(|x| x.path)
+		.unwrap_or_else(|| format!("<proc {}>", proc_id.0));
+
+	if runtime.proc().is_none() {
+		runtime = runtime.with_proc(proc.clone());
+	}
+
+	let handler = ERROR_HANDLER.with(|x| x.borrow().clone());
+
+	let error = HookError {
+		proc: &proc,
+		fn_name,
+		src,
+		usr,
+		runtime,
+	};
+
+	let res = panics::catch(|| match handler {
+		Some(handler) => handler(&error),
+		None => default_hook_error_handler(&error),
+	});
+
+	if let Err(e) = res {
+		eprintln!(
+			"auxtools: hook error handler failed: {} ({})",
+			error.runtime, e
+		);
 	}
 }
 
@@ -149,50 +291,64 @@ extern "C" fn call_proc_by_id_hook(
 	_unknown2: u32,
 	_unknown3: u32,
 ) -> u8 {
-	match PROC_HOOKS.with(|h| match h.borrow().get(&proc_id) {
-		Some(hook) => {
-			let src;
-			let usr;
-			let mut args: Vec<Value>;
-
-			unsafe {
-				src = Value::from_raw(src_raw);
-				usr = Value::from_raw(usr_raw);
-
-				// Taking ownership of args here
-				args = std::slice::from_raw_parts(args_ptr, num_args)
-					.iter()
-					.map(|v| Value::from_raw_owned(*v))
-					.collect();
+	if crate::runtime_event::should_skip_call(proc_id) {
+		// The args are ours to release, like they would be for a hook
+		unsafe {
+			for arg in std::slice::from_raw_parts(args_ptr, num_args) {
+				drop(Value::from_raw_owned(*arg));
 			}
 
-			let result = hook(&src, &usr, &mut args);
-
-			match result {
-				Ok(r) => {
-					let result_raw = (&r).raw;
-					// Stealing our reference out of the Value
-					std::mem::forget(r);
-					Some(result_raw)
-				}
-				Err(e) => {
-					// TODO: Some info about the hook would be useful (as the hook is never part of byond's stack, the runtime won't show it.)
-					Proc::find("/proc/auxtools_stack_trace")
-						.unwrap()
-						.call(&[&Value::from_string(e.message.as_str()).unwrap()])
-						.unwrap();
-					Some(Value::null().raw)
-				}
-			}
+			*ret = Value::null().raw;
 		}
-		None => None,
-	}) {
-		Some(result) => {
-			unsafe {
-				*ret = result;
+		return 1;
+	}
+
+	// The hook is cloned out so that it can (un)hook procs itself without deadlocking
+	let hook = match PROC_HOOKS.with(|h| h.borrow().get(&proc_id).map(|x| x.value().clone())) {
+		Some(hook) => hook,
+		None => return 0,
+	};
+
+	let src;
+	let usr;
+	let mut args: Vec<Value>;
+
+	unsafe {
+		src = Value::from_raw(src_raw);
+		usr = Value::from_raw(usr_raw);
+
+		// Taking ownership of args here
+		args = std::slice::from_raw_parts(args_ptr, num_args)
+			.iter()
+			.map(|v| Value::from_raw_owned(*v))
+			.collect();
+	}
+
+	let result = match panics::catch(|| (hook.func)(&src, &usr, &mut args)) {
+		Ok(Ok(r)) => {
+			let result_raw = (&r).raw;
+			// Stealing our reference out of the Value
+			std::mem::forget(r);
+			result_raw
+		}
+		Ok(Err(e)) => {
+			report_error(proc_id, hook.fn_name, &src, &usr, e);
+			Value::null().raw
+		}
+		Err(mut e) => {
+			if panics::record_failure(panics::PanicSource::ProcHook(proc_id)) {
+				unhook_by_id(proc_id);
+				e.message
+					.push_str("\nThe hook has been disabled after panicking too many times");
 			}
-			1
+
+			report_error(proc_id, hook.fn_name, &src, &usr, e);
+			Value::null().raw
 		}
-		None => 0,
+	};
+
+	unsafe {
+		*ret = result;
 	}
+	1
 }
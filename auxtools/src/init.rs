@@ -1,4 +1,5 @@
 use crate::inventory;
+use std::cell::RefCell;
 
 #[derive(PartialEq, Clone, Copy)]
 pub enum InitLevel {
@@ -22,11 +23,23 @@ pub fn set_init_level(level: InitLevel) {
 //
 pub type InitFunc = fn() -> Result<(), String>;
 
+/// Everything the [init](attr.init.html) macro knows about an init function.
 #[doc(hidden)]
-pub struct FullInitFunc(pub InitFunc);
+pub struct InitFuncInfo {
+	pub func: InitFunc,
+	/// The function's full path, like `my_crate::cache::setup_cache`.
+	pub name: &'static str,
+	/// Higher runs first.
+	pub priority: i32,
+	/// Names of the init functions (of the same kind) that have to run before this one.
+	pub after: &'static [&'static str],
+}
+
+#[doc(hidden)]
+pub struct FullInitFunc(pub InitFuncInfo);
 
 #[doc(hidden)]
-pub struct PartialInitFunc(pub InitFunc);
+pub struct PartialInitFunc(pub InitFuncInfo);
 
 #[doc(hidden)]
 pub struct PartialShutdownFunc(pub fn());
@@ -35,20 +48,213 @@ inventory::collect!(FullInitFunc);
 inventory::collect!(PartialInitFunc);
 inventory::collect!(PartialShutdownFunc);
 
-pub fn run_full_init() -> Result<(), String> {
-	for func in inventory::iter::<FullInitFunc> {
-		func.0()?;
+#[derive(Clone, Debug, PartialEq)]
+pub enum InitOutcome {
+	Succeeded,
+	Failed(String),
+	/// Didn't run, for the given reason.
+	Skipped(String),
+}
+
+/// What happened to a single init function.
+#[derive(Clone, Debug)]
+pub struct InitResult {
+	pub name: &'static str,
+	/// Whether it's a `full` init function rather than a `partial` one.
+	pub full: bool,
+	pub outcome: InitOutcome,
+}
+
+thread_local! {
+	static RESULTS: RefCell<Vec<InitResult>> = RefCell::new(vec![]);
+}
+
+/// What happened to every init function during the last `auxtools_init`, in the order they ran.
+pub fn init_results() -> Vec<InitResult> {
+	RESULTS.with(|x| x.borrow().clone())
+}
+
+pub(crate) fn clear_init_results() {
+	RESULTS.with(|x| x.borrow_mut().clear());
+}
+
+// `after` can name a function by its bare name or any suffix of its path
+fn matches(name: &str, dependency: &str) -> bool {
+	name == dependency
+		|| (name.ends_with(dependency) && name[..name.len() - dependency.len()].ends_with("::"))
+}
+
+// Why `dependency` can't be used, if it doesn't name exactly one function
+fn dependency_error(all: &[&'static InitFuncInfo], dependency: &str) -> Option<String> {
+	let found: Vec<&str> = all
+		.iter()
+		.filter(|other| matches(other.name, dependency))
+		.map(|other| other.name)
+		.collect();
+
+	match found.len() {
+		0 => Some(format!("depends on unknown init function {}", dependency)),
+		1 => None,
+		_ => Some(format!(
+			"depends on {}, which could be any of {}",
+			dependency,
+			found.join(", ")
+		)),
+	}
+}
+
+// Sorts by priority, then makes sure everything comes after its dependencies.
+// Returns the functions in order, plus the ones that can't be ordered and why.
+fn order(
+	mut funcs: Vec<&'static InitFuncInfo>,
+) -> (
+	Vec<&'static InitFuncInfo>,
+	Vec<(&'static InitFuncInfo, String)>,
+) {
+	funcs.sort_by(|a, b| b.priority.cmp(&a.priority).then_with(|| a.name.cmp(b.name)));
+
+	let all = funcs.clone();
+	let mut ordered: Vec<&'static InitFuncInfo> = vec![];
+	let mut remaining = funcs;
+
+	loop {
+		let ready = remaining.iter().position(|func| {
+			func.after.iter().all(|dependency| {
+				ordered.iter().any(|done| matches(done.name, dependency))
+					|| dependency_error(&all, dependency).is_some()
+			})
+		});
+
+		match ready {
+			Some(idx) => ordered.push(remaining.remove(idx)),
+			None => break,
+		}
+	}
+
+	// Unknown and ambiguous dependencies don't hold anything up above, so they're caught here
+	let mut unordered = vec![];
+	let mut idx = 0;
+	while idx < ordered.len() {
+		let func = ordered[idx];
+		match func
+			.after
+			.iter()
+			.find_map(|dependency| dependency_error(&all, dependency))
+		{
+			Some(reason) => unordered.push((ordered.remove(idx), reason)),
+			None => idx += 1,
+		}
 	}
 
-	Ok(())
+	for func in remaining {
+		unordered.push((
+			func,
+			"part of (or waiting on) a dependency cycle".to_owned(),
+		));
+	}
+
+	(ordered, unordered)
 }
 
-pub fn run_partial_init() -> Result<(), String> {
-	for func in inventory::iter::<PartialInitFunc> {
-		func.0()?;
+fn run(
+	funcs: Vec<&'static InitFuncInfo>,
+	full: bool,
+	continue_on_error: bool,
+) -> Result<(), String> {
+	let (ordered, unordered) = order(funcs);
+	let mut errors = vec![];
+
+	let mut failed: Vec<&'static str> = vec![];
+
+	for (func, reason) in unordered {
+		failed.push(func.name);
+
+		let message = format!("{}: {}", func.name, reason);
+		RESULTS.with(|x| {
+			x.borrow_mut().push(InitResult {
+				name: func.name,
+				full,
+				outcome: InitOutcome::Failed(reason),
+			})
+		});
+
+		if !continue_on_error {
+			return Err(message);
+		}
+
+		errors.push(message);
 	}
 
-	Ok(())
+	for func in ordered {
+		let failed_dependency = func
+			.after
+			.iter()
+			.find(|dependency| failed.iter().any(|name| matches(name, dependency)));
+
+		let outcome = match failed_dependency {
+			Some(dependency) => InitOutcome::Skipped(format!("{} failed", dependency)),
+			None => match (func.func)() {
+				Ok(()) => InitOutcome::Succeeded,
+				Err(e) => InitOutcome::Failed(e),
+			},
+		};
+
+		let error = match &outcome {
+			InitOutcome::Succeeded => None,
+			InitOutcome::Failed(e) | InitOutcome::Skipped(e) => {
+				Some(format!("{}: {}", func.name, e))
+			}
+		};
+
+		RESULTS.with(|x| {
+			x.borrow_mut().push(InitResult {
+				name: func.name,
+				full,
+				outcome,
+			})
+		});
+
+		if let Some(error) = error {
+			failed.push(func.name);
+
+			if !continue_on_error {
+				return Err(error);
+			}
+
+			errors.push(error);
+		}
+	}
+
+	if errors.is_empty() {
+		Ok(())
+	} else {
+		Err(errors.join("; "))
+	}
+}
+
+/// Runs every `full` init function. With `continue_on_error` a failure doesn't stop the rest (except for functions
+/// that come `after` it), and the error lists every failure.
+pub fn run_full_init(continue_on_error: bool) -> Result<(), String> {
+	run(
+		inventory::iter::<FullInitFunc>
+			.into_iter()
+			.map(|x| &x.0)
+			.collect(),
+		true,
+		continue_on_error,
+	)
+}
+
+/// Runs every `partial` init function. See [run_full_init](fn.run_full_init.html).
+pub fn run_partial_init(continue_on_error: bool) -> Result<(), String> {
+	run(
+		inventory::iter::<PartialInitFunc>
+			.into_iter()
+			.map(|x| &x.0)
+			.collect(),
+		false,
+		continue_on_error,
+	)
 }
 
 pub fn run_partial_shutdown() {
@@ -56,3 +262,76 @@ pub fn run_partial_shutdown() {
 		func.0();
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn ok() -> Result<(), String> {
+		Ok(())
+	}
+
+	static CONFIG_SETUP: InitFuncInfo = InitFuncInfo {
+		func: ok,
+		name: "config::setup",
+		priority: 0,
+		after: &[],
+	};
+
+	static CACHE_SETUP: InitFuncInfo = InitFuncInfo {
+		func: ok,
+		name: "cache::setup",
+		priority: 0,
+		after: &[],
+	};
+
+	static EAGER: InitFuncInfo = InitFuncInfo {
+		func: ok,
+		name: "eager::start",
+		priority: 100,
+		after: &["config::setup"],
+	};
+
+	static AMBIGUOUS: InitFuncInfo = InitFuncInfo {
+		func: ok,
+		name: "ambiguous::start",
+		priority: 0,
+		after: &["setup"],
+	};
+
+	fn names(funcs: &[&'static InitFuncInfo]) -> Vec<&'static str> {
+		funcs.iter().map(|x| x.name).collect()
+	}
+
+	#[test]
+	fn after_beats_priority() {
+		let (ordered, unordered) = order(vec![&EAGER, &CONFIG_SETUP]);
+		assert_eq!(names(&ordered), ["config::setup", "eager::start"]);
+		assert!(unordered.is_empty());
+	}
+
+	#[test]
+	fn ambiguous_dependency() {
+		let (ordered, unordered) = order(vec![&AMBIGUOUS, &CONFIG_SETUP, &CACHE_SETUP]);
+		assert_eq!(names(&ordered), ["cache::setup", "config::setup"]);
+
+		match unordered.as_slice() {
+			[(func, reason)] => {
+				assert_eq!(func.name, "ambiguous::start");
+				assert!(reason.contains("cache::setup, config::setup"), "{}", reason);
+			}
+			_ => panic!("unordered was {:?}", unordered.len()),
+		}
+	}
+
+	#[test]
+	fn unordered_failure_is_recorded() {
+		clear_init_results();
+		assert!(run(vec![&AMBIGUOUS, &CONFIG_SETUP, &CACHE_SETUP], false, false).is_err());
+
+		let results = init_results();
+		assert_eq!(results.len(), 1);
+		assert_eq!(results[0].name, "ambiguous::start");
+		assert!(matches!(results[0].outcome, InitOutcome::Failed(_)));
+	}
+}
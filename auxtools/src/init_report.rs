@@ -0,0 +1,133 @@
+//! A machine-readable account of what `auxtools_init` did.
+//!
+//! Pass `report` to `auxtools_init` to get the report as JSON instead of `"SUCCESS"` or `"FAILED (...)"`:
+//! ```dm
+//! var/report = json_decode(call(AUXTOOLS_DLL, "auxtools_init")("report"))
+//! if (report["result"] != "SUCCESS")
+//!     CRASH(report["result"])
+//! ```
+//!
+//! Signatures and hooks are only looked at when auxtools needs them, so a partial init (after a reboot) only reports
+//! the ones it used, and a failed init stops reporting at whatever failed.
+
+use crate::hooks::{CompileTimeHook, HookFailure};
+use crate::*;
+use serde::Serialize;
+use std::cell::RefCell;
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct ByondVersion {
+	pub major: u32,
+	pub minor: u32,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SignatureReport {
+	pub name: &'static str,
+	/// Whether init fails without it. Missing optional signatures only disable the APIs that need them.
+	pub required: bool,
+	/// Whether it came from the file loaded by [signature_db](../signature_db/index.html).
+	pub external: bool,
+	pub found: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct HookReport {
+	pub proc: &'static str,
+	/// The Rust function it was hooked to, if it's known.
+	pub function: Option<&'static str>,
+	pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct InitFunctionReport {
+	pub name: &'static str,
+	pub full: bool,
+	/// `succeeded`, `failed` or `skipped`.
+	pub status: &'static str,
+	/// Why it failed or was skipped.
+	pub message: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct InitReport {
+	/// What `auxtools_init` returns without `report`: `"SUCCESS"` or `"FAILED (...)"`.
+	pub result: String,
+	/// Missing if auxtools failed before finding out.
+	pub byond_version: Option<ByondVersion>,
+	pub signatures: Vec<SignatureReport>,
+	pub hooks: Vec<HookReport>,
+	pub init_functions: Vec<InitFunctionReport>,
+}
+
+impl InitReport {
+	pub fn to_json(&self) -> String {
+		serde_json::to_string(self).unwrap()
+	}
+}
+
+thread_local! {
+	static CURRENT: RefCell<InitReport> = RefCell::new(InitReport::default());
+	static LAST: RefCell<Option<InitReport>> = RefCell::new(None);
+}
+
+/// The report for the last `auxtools_init`, whether or not it was asked for.
+pub fn last_report() -> Option<InitReport> {
+	LAST.with(|x| x.borrow().clone())
+}
+
+pub(crate) fn begin() {
+	CURRENT.with(|x| x.replace(InitReport::default()));
+}
+
+pub(crate) fn record_signature(name: &'static str, required: bool, external: bool, found: bool) {
+	CURRENT.with(|x| {
+		x.borrow_mut().signatures.push(SignatureReport {
+			name,
+			required,
+			external,
+			found,
+		})
+	});
+}
+
+pub(crate) fn record_hook(hook: &CompileTimeHook, error: Option<&HookFailure>) {
+	CURRENT.with(|x| {
+		x.borrow_mut().hooks.push(HookReport {
+			proc: hook.proc_path,
+			function: hook.fn_name,
+			error: error.map(|e| format!("{:?}", e)),
+		})
+	});
+}
+
+pub(crate) fn finish(result: &str) -> InitReport {
+	let mut report = CURRENT.with(|x| x.replace(InitReport::default()));
+	report.result = result.to_owned();
+
+	let (major, minor) = version::get();
+	if major != 0 {
+		report.byond_version = Some(ByondVersion { major, minor });
+	}
+
+	report.init_functions = init::init_results()
+		.into_iter()
+		.map(|x| {
+			let (status, message) = match x.outcome {
+				InitOutcome::Succeeded => ("succeeded", None),
+				InitOutcome::Failed(e) => ("failed", Some(e)),
+				InitOutcome::Skipped(e) => ("skipped", Some(e)),
+			};
+
+			InitFunctionReport {
+				name: x.name,
+				full: x.full,
+				status,
+				message,
+			}
+		})
+		.collect();
+
+	LAST.with(|x| x.replace(Some(report.clone())));
+	report
+}
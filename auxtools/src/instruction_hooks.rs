@@ -0,0 +1,526 @@
+//! Runs Rust code when BYOND executes particular instructions.
+//!
+//! A hooked instruction is overwritten with a special opcode that BYOND doesn't know about. When it comes up we put
+//! the original instruction back just long enough for BYOND to run it, so several hooks can share an instruction and
+//! none of them change what the proc actually does.
+//!
+//! Observers are called before every single instruction, which is what the debugger uses for stepping. They're
+//! expensive, so only use one if you really need to see everything.
+//!
+//! Nothing is detoured until the first hook or observer is added.
+
+use crate::*;
+use detour::RawDetour;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::rc::Rc;
+
+const OPCODE_DEBUG_BREAK: u32 = 0x1337;
+const OPCODE_DEBUG_OPERAND: u32 = 0x1338;
+
+extern "C" {
+	// Trampoline to the original un-hooked BYOND execute_instruction code
+	static mut execute_instruction_original: *const c_void;
+
+	// Our version of execute_instruction. It hasn't got a calling convention rust knows about, so don't call it.
+	fn execute_instruction_hook();
+}
+
+/// Called with the context that is about to execute the instruction.
+pub type InstructionHook = Rc<dyn Fn(*mut raw_types::procs::ExecutionContext)>;
+
+/// Identifies a single hook or observer so it can be removed without affecting anyone else's.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct InstructionHookId(u64);
+
+#[derive(Debug)]
+pub enum InstructionHookError {
+	/// Couldn't find or detour BYOND's instruction handler.
+	Unavailable(String),
+	/// There is no instruction starting at this offset.
+	InvalidOffset,
+	/// The instruction is a [custom opcode](../custom_opcodes/index.html), which runs before hooks would get to see it.
+	CustomOpcode,
+}
+
+impl std::fmt::Display for InstructionHookError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Unavailable(e) => write!(f, "instruction hooks are unavailable: {}", e),
+			Self::InvalidOffset => write!(f, "no instruction at the given offset"),
+			Self::CustomOpcode => write!(f, "custom opcodes can't be hooked"),
+		}
+	}
+}
+
+struct HookedInstruction {
+	original: Vec<u32>,
+	hooks: Rc<Vec<(InstructionHookId, InstructionHook)>>,
+}
+
+#[derive(Default)]
+struct State {
+	next_id: u64,
+	// Keyed by the address of the hooked instruction
+	instructions: HashMap<usize, HookedInstruction>,
+	ids: HashMap<InstructionHookId, usize>,
+	observers: Rc<Vec<(InstructionHookId, InstructionHook)>>,
+	// The instruction we let BYOND run normally last time, which needs hooking again before anything else happens
+	deferred: Option<(Vec<u32>, *mut u32)>,
+	// The length of the instruction at each offset, keyed by the address of the bytecode. Replaced bytecode is kept
+	// alive until shutdown, so an address never gets reused for different code.
+	lengths: HashMap<usize, Rc<HashMap<u32, usize>>>,
+}
+
+impl State {
+	fn next_id(&mut self) -> InstructionHookId {
+		self.next_id += 1;
+		InstructionHookId(self.next_id)
+	}
+}
+
+thread_local! {
+	static STATE: RefCell<State> = RefCell::new(State::default());
+}
+
+pub(crate) fn enable() -> Result<(), InstructionHookError> {
+	static mut ENABLED: bool = false;
+
+	unsafe {
+		if ENABLED {
+			return Ok(());
+		}
+	}
+
+	let byondcore = sigscan::Scanner::for_module(BYONDCORE)
+		.ok_or_else(|| InstructionHookError::Unavailable("Couldn't scan BYONDCORE".into()))?;
+
+	let execute_instruction = if cfg!(windows) {
+		byondcore.find(signature!(
+			"0F B7 48 ?? 8B 78 ?? 8B F1 8B 14 ?? 81 FA ?? ?? 00 00 0F 87 ?? ?? ?? ??"
+		))
+	} else {
+		byondcore.find(signature!(
+			"0F B7 47 ?? 8B 57 ?? 0F B7 D8 8B 0C ?? 81 F9 ?? ?? 00 00 77 ?? FF 24 8D ?? ?? ?? ??"
+		))
+	}
+	.ok_or_else(|| InstructionHookError::Unavailable("Couldn't find EXECUTE_INSTRUCTION".into()))?;
+
+	unsafe {
+		let hook = RawDetour::new(
+			execute_instruction as *const (),
+			execute_instruction_hook as *const (),
+		)
+		.map_err(|_| {
+			InstructionHookError::Unavailable("Couldn't detour EXECUTE_INSTRUCTION".into())
+		})?;
+
+		hook.enable().map_err(|_| {
+			InstructionHookError::Unavailable("Couldn't enable EXECUTE_INSTRUCTION detour".into())
+		})?;
+
+		execute_instruction_original = std::mem::transmute(hook.trampoline());
+
+		// We never remove or disable the hook, so just forget about it.
+		std::mem::forget(hook);
+
+		ENABLED = true;
+	}
+
+	Ok(())
+}
+
+// Returns the length of the instruction at `offset`, ignoring any hooks in the way
+fn instruction_length(proc: &Proc, offset: u32) -> Option<usize> {
+	let key = unsafe { proc.bytecode() }.as_ptr() as usize;
+
+	let lengths = match STATE.with(|state| state.borrow().lengths.get(&key).cloned()) {
+		Some(lengths) => lengths,
+		None => {
+			let bytecode = unhooked_bytecode(proc);
+
+			let mut env = DisassembleEnv;
+			let (nodes, _error) = dmasm::disassembler::disassemble(&bytecode, &mut env);
+
+			let lengths: HashMap<u32, usize> = nodes
+				.into_iter()
+				.filter_map(|node| match node {
+					dmasm::Node::Instruction(_, debug) => {
+						Some((debug.offset, debug.bytecode.len()))
+					}
+					_ => None,
+				})
+				.collect();
+
+			let lengths = Rc::new(lengths);
+			STATE.with(|state| state.borrow_mut().lengths.insert(key, lengths.clone()));
+			lengths
+		}
+	};
+
+	lengths.get(&offset).copied()
+}
+
+/// Whether an instruction starts at `offset` in `proc`'s current bytecode.
+pub fn is_instruction(proc: &Proc, offset: u32) -> bool {
+	instruction_length(proc, offset).is_some()
+}
+
+/// Calls `hook` every time `proc` is about to execute the instruction starting at `offset`.
+///
+/// Any number of hooks can be added to the same instruction. They're called in the order they were added.
+pub fn hook<F>(proc: &Proc, offset: u32, hook: F) -> Result<InstructionHookId, InstructionHookError>
+where
+	F: Fn(*mut raw_types::procs::ExecutionContext) + 'static,
+{
+	enable()?;
+
+	let length = instruction_length(proc, offset).ok_or(InstructionHookError::InvalidOffset)?;
+
+	let bytecode = unsafe {
+		let (ptr, count) = proc.bytecode_mut_ptr();
+		std::slice::from_raw_parts_mut(ptr, count as usize)
+	};
+
+	if crate::custom_opcodes::is_custom(bytecode[offset as usize]) {
+		return Err(InstructionHookError::CustomOpcode);
+	}
+
+	let opcode_ptr = unsafe { bytecode.as_mut_ptr().add(offset as usize) };
+
+	STATE.with(|state| {
+		let mut state = state.borrow_mut();
+		let id = state.next_id();
+
+		let instruction = state
+			.instructions
+			.entry(opcode_ptr as usize)
+			.or_insert_with(|| {
+				let original = bytecode[offset as usize..offset as usize + length].to_vec();
+
+				bytecode[offset as usize] = OPCODE_DEBUG_BREAK;
+				for operand in &mut bytecode[offset as usize + 1..offset as usize + length] {
+					*operand = OPCODE_DEBUG_OPERAND;
+				}
+
+				HookedInstruction {
+					original,
+					hooks: Rc::new(vec![]),
+				}
+			});
+
+		Rc::make_mut(&mut instruction.hooks).push((id, Rc::new(hook)));
+		state.ids.insert(id, opcode_ptr as usize);
+
+		Ok(id)
+	})
+}
+
+/// Removes a hook added by [hook](fn.hook.html). The instruction goes back to normal once it has no hooks left.
+///
+/// Returns false if the hook didn't exist.
+pub fn unhook(id: InstructionHookId) -> bool {
+	STATE.with(|state| {
+		let mut state = state.borrow_mut();
+
+		let ptr = match state.ids.remove(&id) {
+			Some(ptr) => ptr,
+			None => return false,
+		};
+
+		let instruction = state.instructions.get_mut(&ptr).unwrap();
+		Rc::make_mut(&mut instruction.hooks).retain(|(x, _)| *x != id);
+
+		if instruction.hooks.is_empty() {
+			let instruction = state.instructions.remove(&ptr).unwrap();
+			let opcode_ptr = ptr as *mut u32;
+
+			// If the original is already in place, make sure it stays there
+			if let Some((_, dst)) = state.deferred {
+				if dst == opcode_ptr {
+					state.deferred = None;
+				}
+			}
+
+			unsafe {
+				std::ptr::copy_nonoverlapping(
+					instruction.original.as_ptr(),
+					opcode_ptr,
+					instruction.original.len(),
+				);
+			}
+		}
+
+		true
+	})
+}
+
+/// Calls `observer` before every instruction BYOND executes.
+pub fn add_observer<F>(observer: F) -> Result<InstructionHookId, InstructionHookError>
+where
+	F: Fn(*mut raw_types::procs::ExecutionContext) + 'static,
+{
+	enable()?;
+
+	STATE.with(|state| {
+		let mut state = state.borrow_mut();
+		let id = state.next_id();
+		Rc::make_mut(&mut state.observers).push((id, Rc::new(observer)));
+		Ok(id)
+	})
+}
+
+/// Removes an observer added by [add_observer](fn.add_observer.html). Returns false if it didn't exist.
+pub fn remove_observer(id: InstructionHookId) -> bool {
+	STATE.with(|state| {
+		let mut state = state.borrow_mut();
+		let observers = Rc::make_mut(&mut state.observers);
+		let len = observers.len();
+		observers.retain(|(x, _)| *x != id);
+		observers.len() != len
+	})
+}
+
+// Calls `f` with the offset and original bytecode of every hooked instruction in `bytecode`
+fn visit_hooked<F: FnMut(usize, &[u32])>(bytecode: &[u32], mut f: F) {
+	let start = bytecode.as_ptr() as usize;
+	let end = start + bytecode.len() * std::mem::size_of::<u32>();
+
+	STATE.with(|state| {
+		for (addr, instruction) in &state.borrow().instructions {
+			if (start..end).contains(addr) {
+				f(
+					(addr - start) / std::mem::size_of::<u32>(),
+					&instruction.original,
+				);
+			}
+		}
+	});
+}
+
+fn unhooked(bytecode: &[u32]) -> Vec<u32> {
+	let mut res = bytecode.to_vec();
+
+	visit_hooked(bytecode, |offset, original| {
+		res[offset..offset + original.len()].copy_from_slice(original);
+	});
+
+	crate::custom_opcodes::mask(bytecode, &mut res);
+	res
+}
+
+/// Offsets of every hooked instruction in `proc`, sorted.
+pub fn hooked_offsets(proc: &Proc) -> Vec<u32> {
+	let mut offsets = vec![];
+	visit_hooked(unsafe { proc.bytecode() }, |offset, _| {
+		offsets.push(offset as u32)
+	});
+	offsets.sort_unstable();
+	offsets
+}
+
+/// A copy of `proc`'s bytecode with every hooked instruction put back how it was, for disassembling.
+///
+/// [Custom opcodes](../custom_opcodes/index.html) show up as the global proc calls they were assembled from.
+pub fn unhooked_bytecode(proc: &Proc) -> Vec<u32> {
+	unhooked(unsafe { proc.bytecode() })
+}
+
+/// Like [unhooked_bytecode](fn.unhooked_bytecode.html), but for the bytecode `proc` was compiled with.
+pub fn unhooked_original_bytecode(proc: &Proc) -> Vec<u32> {
+	unhooked(unsafe { proc.original_bytecode() })
+}
+
+/// The opcode of the instruction `ctx` is about to execute, as it was before any hook replaced it.
+///
+/// Observers run before a hooked instruction is put back for BYOND to execute, so they should use this rather than
+/// reading the bytecode themselves.
+pub fn current_opcode(ctx: *mut raw_types::procs::ExecutionContext) -> u32 {
+	let opcode_ptr = unsafe { (*ctx).bytecode.add((*ctx).bytecode_offset as usize) };
+
+	let opcode = unsafe { *opcode_ptr };
+	if opcode != OPCODE_DEBUG_BREAK {
+		return opcode;
+	}
+
+	STATE.with(|state| {
+		state
+			.borrow()
+			.instructions
+			.get(&(opcode_ptr as usize))
+			.map_or(opcode, |x| x.original[0])
+	})
+}
+
+/// Whether a hook or observer is still installed. Hooks go away on their own when their proc's bytecode is replaced.
+pub fn is_active(id: InstructionHookId) -> bool {
+	STATE.with(|state| {
+		let state = state.borrow();
+		state.ids.contains_key(&id) || state.observers.iter().any(|(x, _)| *x == id)
+	})
+}
+
+/// Removes every hook on `proc`'s current bytecode and returns their ids.
+///
+/// This happens automatically before a proc's bytecode is replaced, because the offsets they were added at
+/// don't mean anything in the new code.
+pub fn unhook_proc(proc: &Proc) -> Vec<InstructionHookId> {
+	let (ptr, count) = unsafe { proc.bytecode_mut_ptr() };
+	let start = ptr as usize;
+	let end = start + count as usize * std::mem::size_of::<u32>();
+
+	let ids: Vec<InstructionHookId> = STATE.with(|state| {
+		state
+			.borrow()
+			.ids
+			.iter()
+			.filter(|(_, addr)| (start..end).contains(*addr))
+			.map(|(id, _)| *id)
+			.collect()
+	});
+
+	for id in &ids {
+		unhook(*id);
+	}
+
+	ids
+}
+
+impl Proc {
+	/// Shorthand for [instruction_hooks::hook](instruction_hooks/fn.hook.html).
+	pub fn hook_instruction<F>(
+		&self,
+		offset: u32,
+		hook: F,
+	) -> Result<InstructionHookId, InstructionHookError>
+	where
+		F: Fn(*mut raw_types::procs::ExecutionContext) + 'static,
+	{
+		self::hook(self, offset, hook)
+	}
+}
+
+pub(crate) fn shutdown() {
+	STATE.with(|state| {
+		let mut state = state.borrow_mut();
+
+		// The deferred instruction's original is in place already, so it just needs forgetting about
+		state.deferred = None;
+
+		for (ptr, instruction) in state.instructions.drain() {
+			unsafe {
+				std::ptr::copy_nonoverlapping(
+					instruction.original.as_ptr(),
+					ptr as *mut u32,
+					instruction.original.len(),
+				);
+			}
+		}
+
+		state.ids.clear();
+		state.observers = Rc::new(vec![]);
+		state.lengths.clear();
+	});
+}
+
+fn run_hook(
+	id: InstructionHookId,
+	hook: &InstructionHook,
+	ctx: *mut raw_types::procs::ExecutionContext,
+) {
+	if let Err(mut e) = panics::catch(|| hook(ctx)) {
+		if panics::record_failure(panics::PanicSource::InstructionHook(id)) {
+			if !unhook(id) {
+				remove_observer(id);
+			}
+
+			e.message.push_str(
+				"\nThe instruction hook has been disabled after panicking too many times",
+			);
+		}
+
+		crate::runtime::report_runtime(&e.at_current_location());
+	}
+}
+
+// Puts the hook back on the instruction we last let BYOND run normally
+fn restore_deferred(state: &mut State) {
+	if let Some((src, dst)) = state.deferred.take() {
+		unsafe {
+			std::ptr::copy_nonoverlapping(src.as_ptr(), dst, src.len());
+		}
+	}
+}
+
+fn handle_instruction_inner(ctx: *mut raw_types::procs::ExecutionContext) {
+	// Always handle the deferred instruction replacement first - everything else will depend on it
+	STATE.with(|state| restore_deferred(&mut state.borrow_mut()));
+
+	// BYOND would choke on these, so they have to be out of the way before anything else sees the instruction
+	crate::custom_opcodes::dispatch(ctx);
+
+	let opcode_ptr = unsafe { (*ctx).bytecode.add((*ctx).bytecode_offset as usize) };
+
+	let (observers, hooks) = STATE.with(|state| {
+		let state = state.borrow();
+
+		let mut hooks = None;
+		if unsafe { *opcode_ptr } == OPCODE_DEBUG_BREAK {
+			hooks = state
+				.instructions
+				.get(&(opcode_ptr as usize))
+				.map(|x| x.hooks.clone());
+		}
+
+		(state.observers.clone(), hooks)
+	});
+
+	// Nothing is borrowed at this point, so these are free to add or remove hooks.
+	// They can also run DM (like the debugger evaluating something), which goes through here for its own instructions.
+	for (id, observer) in observers.iter() {
+		run_hook(*id, observer, ctx);
+	}
+
+	if let Some(hooks) = hooks {
+		for (id, hook) in hooks.iter() {
+			run_hook(*id, hook, ctx);
+		}
+	}
+
+	STATE.with(|state| {
+		let state = &mut *state.borrow_mut();
+
+		// Whatever DM the hooks ran may have left an instruction deferred. It has finished running by now.
+		restore_deferred(state);
+
+		if unsafe { *opcode_ptr } != OPCODE_DEBUG_BREAK {
+			return;
+		}
+
+		// Let BYOND run the real instruction this time, and hook it again before the next one.
+		// This has to come after the hooks, or DM they run would put the hook back before BYOND gets here.
+		if let Some(instruction) = state.instructions.get(&(opcode_ptr as usize)) {
+			let len = instruction.original.len();
+
+			unsafe {
+				let hooked = std::slice::from_raw_parts(opcode_ptr, len).to_vec();
+				std::ptr::copy_nonoverlapping(instruction.original.as_ptr(), opcode_ptr, len);
+				state.deferred = Some((hooked, opcode_ptr));
+			}
+		}
+	});
+}
+
+// Handles any instruction BYOND tries to execute.
+// This function has to leave `*CURRENT_EXECUTION_CONTEXT` in EAX, so make sure to return it.
+#[no_mangle]
+extern "C" fn handle_instruction(
+	ctx: *mut raw_types::procs::ExecutionContext,
+) -> *const raw_types::procs::ExecutionContext {
+	if let Err(e) = panics::catch(|| handle_instruction_inner(ctx)) {
+		crate::runtime::report_runtime(&e.at_current_location());
+	}
+
+	ctx
+}
@@ -5,38 +5,72 @@
 //#[cfg(not(target_pointer_width = "32"))]
 //compile_error!("Auxtools must be compiled for a 32-bit target");
 
+mod assemble_env;
 mod byond_ffi;
 mod bytecode_manager;
+mod bytecode_patch;
+pub mod call_graph;
+mod compiler;
+pub mod custom_opcodes;
 pub mod debug;
+mod disassemble_env;
+pub mod disassembly_dump;
 mod hooks;
 mod init;
+pub mod init_report;
+pub mod instruction_hooks;
 mod list;
+pub mod optimizer;
+pub mod panics;
 mod proc;
 pub mod raw_types;
 mod runtime;
+pub mod runtime_aggregator;
+mod runtime_event;
+pub mod signature_db;
 pub mod sigscan;
 mod string;
 mod string_intern;
+pub mod strings;
+mod text_macros;
 mod value;
+mod variable_intern;
 mod version;
+pub mod xref;
 
 use init::{get_init_level, set_init_level, InitLevel};
 
+pub use assemble_env::AssembleEnv;
 pub use auxtools_impl::{hook, init, runtime_handler, shutdown};
-pub use hooks::{CompileTimeHook, RuntimeHook};
-pub use init::{FullInitFunc, PartialInitFunc, PartialShutdownFunc};
+pub use bytecode_patch::{BytecodePatch, PatchError};
+pub use compiler::CompileError;
+pub use disassemble_env::DisassembleEnv;
+pub use hooks::{
+	default_hook_error_handler, reset_hook_error_handler, set_hook_error_handler, CompileTimeHook,
+	HookError, RuntimeHook,
+};
+pub use init::{
+	init_results, FullInitFunc, InitFuncInfo, InitOutcome, InitResult, PartialInitFunc,
+	PartialShutdownFunc,
+};
 pub use list::List;
-pub use proc::Proc;
+pub use proc::{Proc, ProcFlags};
 pub use raw_types::variables::VariableNameIdTable;
-pub use runtime::{DMResult, Runtime};
+pub use runtime::{catch_runtimes, collect_runtimes, DMResult, Runtime};
+pub use runtime_event::RuntimeEvent;
 use std::ffi::c_void;
 pub use string::StringRef;
 pub use string_intern::InternedString;
+pub use text_macros::{TextBuilder, TextMacro, TextToken};
 pub use value::Value;
+pub use variable_intern::InternedVariable;
 
 /// Used by the [hook](attr.hook.html) macro to aggregate all compile-time hooks
 pub use inventory;
 
+/// Used by [BytecodePatch](struct.BytecodePatch.html) to (dis)assemble bytecode
+pub use dmasm;
+
 // We need winapi to call GetModuleHandleExW which lets us prevent our DLL from unloading.
 #[cfg(windows)]
 extern crate winapi;
@@ -50,6 +84,7 @@ signatures! {
 	call_proc_by_id => "E8 ?? ?? ?? ?? 83 C4 2C 89 45 F4 89 55 F8 8B 45 F4 8B 55 F8 5F 5E 5B 8B E5 5D C3 CC 55 8B EC 83 EC 0C 53 8B 5D 10 8D 45 FF",
 	get_variable => "55 8B EC 8B 4D ?? 0F B6 C1 48 83 F8 ?? 0F 87 ?? ?? ?? ?? 0F B6 80 ?? ?? ?? ?? FF 24 85 ?? ?? ?? ?? FF 75 ?? FF 75 ?? E8",
 	get_string_table_entry => "55 8B EC 8B 4D 08 3B 0D ?? ?? ?? ?? 73 10 A1",
+	string_table_count => "55 8B EC 8B 4D 08 3B 0D ?? ?? ?? ?? 73 10 A1",
 	call_datum_proc_by_name => "55 8B EC 83 EC 0C 53 8B 5D 10 8D 45 FF 56 8B 75 14 57 6A 01 50 FF 75 1C C6 45 FF 00 FF 75 18 6A 00 56",
 	dec_ref_count => "E8 ?? ?? ?? ?? 83 C4 0C 81 FF FF FF 00 00 74 ?? 85 FF 74 ?? 57 FF 75 ??",
 	inc_ref_count => "E8 ?? ?? ?? ?? FF 77 ?? FF 77 ?? E8 ?? ?? ?? ?? 8D 77 ?? 56 E8 ?? ?? ?? ??",
@@ -62,7 +97,12 @@ signatures! {
 	get_misc_by_id => "E8 ?? ?? ?? ?? 83 C4 04 85 C0 75 ?? FF 75 ?? E8 ?? ?? ?? ?? FF 30 68 ?? ?? ?? ?? E8 ?? ?? ?? ?? A1 ?? ?? ?? ??",
 	runtime => "E8 ?? ?? ?? ?? 83 C4 04 8B 85 ?? ?? ?? ?? 0F B6 C0 51 66 0F 6E C0 0F 5B C0",
 	suspended_procs => "A1 ?? ?? ?? ?? 8B D8 89 45 ?? 89 75 ?? 3B DA 73 ?? 8D 0C ?? D1 E9 8B 04 ??",
-	suspended_procs_buffer => "8B 35 ?? ?? ?? ?? 8B 80 ?? ?? ?? ?? 57 8B 3D ?? ?? ?? ?? 8B D7 89 45 ??"
+	suspended_procs_buffer => "8B 35 ?? ?? ?? ?? 8B 80 ?? ?? ?? ?? 57 8B 3D ?? ?? ?? ?? 8B D7 89 45 ??",
+	to_string_513 => "55 8B EC 6A FF 68 ?? ?? ?? ?? 64 A1 ?? ?? ?? ?? 50 83 EC 10 53 56 57 A1 ?? ?? ?? ?? 33 C5 50 8D 45 ?? 64 A3 ?? ?? ?? ?? 8B 5D ?? 0F B6 C3",
+	to_string_514 => "55 8B EC 6A FF 68 ?? ?? ?? ?? 64 A1 ?? ?? ?? ?? 50 83 EC 14 53 56 57 A1 ?? ?? ?? ?? 33 C5 50 8D 45 ?? 64 A3 ?? ?? ?? ?? 8B 4D ??",
+	set_variable => "55 8B EC 8B 4D 08 0F B6 C1 48 57 8B 7D 10 83 F8 53 0F ?? ?? ?? ?? ?? 0F B6 80 ?? ?? ?? ?? FF 24 85 ?? ?? ?? ?? FF 75 18 FF 75 14 57 FF 75 0C E8 ?? ?? ?? ?? 83 C4 10 5F 5D C3",
+	current_execution_context => "A1 ?? ?? ?? ?? FF 75 ?? 89 4D ?? 8B 4D ?? 8B 00 6A 00 52 6A 12 FF 70 ??",
+	variable_names => "8B 1D ?? ?? ?? ?? 2B 0C ?? 8B 5D ?? 74 ?? 85 C9 79 ?? 0F B7 D0 EB ?? 83 C0 02"
 }
 
 #[cfg(unix)]
@@ -74,6 +114,7 @@ signatures! {
 	call_proc_by_id => "E8 ?? ?? ?? ?? 8B 45 ?? 8B 55 ?? 89 45 ?? 89 55 ?? 8B 55 ?? 8B 4D ?? 8B 5D ??",
 	get_variable => "55 89 E5 81 EC C8 00 00 00 8B 55 ?? 89 5D ?? 8B 5D ?? 89 75 ?? 8B 75 ??",
 	get_string_table_entry => "55 89 E5 83 EC 18 8B 45 ?? 39 05 ?? ?? ?? ?? 76 ?? 8B 15 ?? ?? ?? ?? 8B 04 ??",
+	string_table_count => "55 89 E5 83 EC 18 8B 45 ?? 39 05 ?? ?? ?? ?? 76 ?? 8B 15 ?? ?? ?? ?? 8B 04 ??",
 	call_datum_proc_by_name => "55 89 E5 57 56 53 83 EC 5C 8B 55 ?? 0F B6 45 ?? 8B 4D ?? 8B 5D ?? 89 14 24 8B 55 ?? 88 45 ?? 0F B6 F8 8B 75 ?? 8D 45 ?? 89 44 24 ?? 89 F8 89 4C 24 ?? 31 C9 C6 45 ?? 00 C7 44 24 ?? 01 00 00 00",
 	dec_ref_count_513 => "E8 ?? ?? ?? ?? 8B 4D ?? C7 44 24 ?? 00 00 00 00 C7 44 24 ?? 00 00 00 00 89 0C 24",
 	dec_ref_count_514 => "E8 ?? ?? ?? ?? C7 06 00 00 00 00 C7 46 ?? 00 00 00 00 A1 ?? ?? ?? ?? 0F B7 50 ??",
@@ -87,18 +128,46 @@ signatures! {
 	get_misc_by_id => "E8 ?? ?? ?? ?? 0F B7 55 ?? 03 1F 0F B7 4B ?? 89 8D ?? ?? ?? ?? 0F B7 5B ??",
 	runtime => "E8 ?? ?? ?? ?? 31 C0 8D B4 26 00 00 00 00 8B 5D ?? 8B 75 ?? 8B 7D ?? 89 EC",
 	suspended_procs => "A3 ?? ?? ?? ?? 8D 14 ?? 73 ?? 8D 74 26 00 83 C0 01 8B 14 ?? 39 C3 89 54 ?? ??",
-	suspended_procs_buffer => "89 35 ?? ?? ?? ?? C7 04 24 ?? ?? ?? ?? E8 ?? ?? ?? ?? 8B 45 ?? 83 C0 08"
+	suspended_procs_buffer => "89 35 ?? ?? ?? ?? C7 04 24 ?? ?? ?? ?? E8 ?? ?? ?? ?? 8B 45 ?? 83 C0 08",
+	to_string_513 => "55 89 E5 83 EC 58 89 5D ?? 8B 5D ?? 89 75 ?? 8B 75 ?? 89 7D ?? 80 FB 54",
+	to_string_514 => "55 89 E5 83 EC 68 A1 ?? ?? ?? ?? 8B 15 ?? ?? ?? ?? 8B 0D ?? ?? ?? ?? 89 5D ??",
+	set_variable_513 => "55 89 E5 81 EC A8 00 00 00 8B 55 ?? 8B 45 ?? 89 5D ?? 8B 5D ?? 89 7D ??",
+	set_variable_514 => "55 89 E5 81 EC A8 00 00 00 8B 55 ?? 89 5D ?? 8B 4D ?? 89 7D ?? 8B 5D ??",
+	current_execution_context => "A1 ?? ?? ?? ?? 85 C0 0F 84 ?? ?? ?? ?? 8B 00 85 C0 0F 84 ?? ?? ?? ?? 8B 00",
+	variable_names_513 => "8B 35 ?? ?? ?? ?? 89 5D ?? 0F B7 08 89 75 ?? 66 C7 45 ?? 00 00 89 7D ??",
+	variable_names_514 => "A1 ?? ?? ?? ?? 8B 13 8B 39 8B 75 ?? 8B 14 ?? 89 7D ?? 8B 3C ?? 83 EE 02"
+}
+
+// Prefers a signature loaded by signature_db over the built-in one
+fn find_signature(
+	scanner: &sigscan::Scanner,
+	name: &'static str,
+	required: bool,
+	builtin: &[Option<u8>],
+) -> Option<*mut u8> {
+	let (res, external) = match signature_db::get_with_offset(name) {
+		Some((signature, offset)) => (
+			scanner
+				.find(&signature)
+				.map(|ptr| unsafe { ptr.add(offset) }),
+			true,
+		),
+		None => (scanner.find(builtin), false),
+	};
+	init_report::record_signature(name, required, external, res.is_some());
+	res
 }
 
 macro_rules! find_function {
 	($scanner:ident, $name:ident) => {
 		let $name: *const c_void;
-		if let Some(ptr) = $scanner.find(SIGNATURES.$name) {
+		let res = find_signature(&$scanner, stringify!($name), true, SIGNATURES.$name);
+		if let Some(ptr) = res {
 			unsafe {
 				$name = std::mem::transmute(ptr as *const c_void);
 			}
 		} else {
-			return Some(format!("FAILED (Couldn't find {})", stringify!($name)));
+			return format!("FAILED (Couldn't find {})", stringify!($name));
 		}
 	};
 }
@@ -106,17 +175,29 @@ macro_rules! find_function {
 macro_rules! find_function_by_call {
 	($scanner:ident, $name:ident) => {
 		let $name: *const c_void;
-		if let Some(ptr) = $scanner.find(SIGNATURES.$name) {
+		let res = find_signature(&$scanner, stringify!($name), true, SIGNATURES.$name);
+		if let Some(ptr) = res {
 			unsafe {
 				let offset = *(ptr.offset(1) as *const isize);
 				$name = ptr.offset(5).offset(offset) as *const () as *const std::ffi::c_void;
 			}
 		} else {
-			return Some(format!("FAILED (Couldn't find {})", stringify!($name)));
+			return format!("FAILED (Couldn't find {})", stringify!($name));
 		}
 	};
 }
 
+// Leaves the function null if it isn't found. Anything that uses it has to check with require_function first.
+macro_rules! find_optional_function {
+	($scanner:ident, $name:ident) => {
+		let res = find_signature(&$scanner, stringify!($name), false, SIGNATURES.$name);
+		let $name: *const c_void = match res {
+			Some(ptr) => ptr as *const c_void,
+			None => std::ptr::null(),
+		};
+	};
+}
+
 macro_rules! with_scanner {
 	($scanner:ident, $( $name:ident),* ) => {
 		$( find_function!($scanner, $name); )*
@@ -129,6 +210,24 @@ macro_rules! with_scanner_by_call {
 	};
 }
 
+macro_rules! with_optional_scanner {
+	($scanner:ident, $( $name:ident),* ) => {
+		$( find_optional_function!($scanner, $name); )*
+	};
+}
+
+// Fails with a runtime if an optional BYOND function wasn't found during init
+pub(crate) fn require_function(func: *const c_void, name: &str) -> DMResult<()> {
+	if func.is_null() {
+		return Err(Runtime::new(format!(
+			"{} is unavailable: its signature wasn't found in this BYOND version",
+			name
+		)));
+	}
+
+	Ok(())
+}
+
 // This strange section of code retrieves our DLL using the init function's address.
 // This increments the DLL reference count, which prevents unloading.
 #[cfg(windows)]
@@ -157,14 +256,75 @@ fn pin_dll() -> Result<(), ()> {
 	Ok(())
 }
 
-byond_ffi_fn! { auxtools_init(_input) {
+// Splits the argument of auxtools_init on spaces and commas, except inside double quotes
+fn parse_flags(input: &str) -> Vec<String> {
+	let mut flags = vec![];
+	let mut current = String::new();
+	let mut quoted = false;
+
+	for c in input.chars() {
+		match c {
+			'"' => quoted = !quoted,
+			c if !quoted && (c == ',' || c.is_whitespace()) => {
+				if !current.is_empty() {
+					flags.push(std::mem::take(&mut current));
+				}
+			}
+			c => current.push(c),
+		}
+	}
+
+	if !current.is_empty() {
+		flags.push(current);
+	}
+
+	flags
+}
+
+// Flags are passed as the argument, separated by spaces or commas:
+//   continue_on_error: run every init function even if some fail, and list every failure
+//   report: return an init_report::InitReport as JSON instead of "SUCCESS" or "FAILED (...)"
+//   signatures=<path>: load signatures from a file, see signature_db
+// Anything in double quotes isn't split, like signatures="C:\My Server\signatures.json"
+byond_ffi_fn! { auxtools_init(input) {
+	panics::install_hook();
+	init::clear_init_results();
+	init_report::begin();
+
+	let flags = parse_flags(input);
+	let continue_on_error = flags.iter().any(|x| x == "continue_on_error");
+
+	let signature_file = flags.iter().find_map(|x| x.strip_prefix("signatures="));
+	let loaded = match signature_file {
+		Some(path) => signature_db::load(path),
+		None => {
+			signature_db::clear();
+			Ok(())
+		}
+	};
+
+	let result = match loaded {
+		Ok(()) => initialize(continue_on_error),
+		Err(e) => format!("FAILED (Couldn't load signatures: {})", e),
+	};
+
+	let report = init_report::finish(&result);
+
+	if flags.iter().any(|x| x == "report") {
+		Some(report.to_json())
+	} else {
+		Some(result)
+	}
+} }
+
+fn initialize(continue_on_error: bool) -> String {
 	if get_init_level() == InitLevel::None {
-		return Some("SUCCESS".to_owned())
+		return "SUCCESS".to_owned();
 	}
 
 	let byondcore = match sigscan::Scanner::for_module(BYONDCORE) {
 		Some(v) => v,
-		None => return Some("FAILED (Couldn't create scanner for byondcore.dll)".to_owned())
+		None => return "FAILED (Couldn't create scanner for byondcore.dll)".to_owned(),
 	};
 
 	let mut did_full = false;
@@ -173,24 +333,26 @@ byond_ffi_fn! { auxtools_init(_input) {
 	if get_init_level() == InitLevel::Full {
 		did_full = true;
 		if let Err(e) = version::init() {
-			return Some(format!("FAILED ({})", e));
+			return format!("FAILED ({})", e);
 		}
 
-
-
 		with_scanner! { byondcore,
 			get_string_id,
 			get_variable,
 			get_string_table_entry,
 			call_datum_proc_by_name,
+			create_list,
+			suspended_procs,
+			suspended_procs_buffer
+		}
+
+		// Only used by the List and to_string APIs, which fail on their own if these are missing
+		with_optional_scanner! { byondcore,
 			get_assoc_element,
 			set_assoc_element,
 			append_to_list,
 			remove_from_list,
-			get_length,
-			create_list,
-			suspended_procs,
-			suspended_procs_buffer
+			get_length
 		}
 
 		with_scanner_by_call! { byondcore,
@@ -233,90 +395,71 @@ byond_ffi_fn! { auxtools_init(_input) {
 			}
 		}
 
-		let mut to_string = std::ptr::null();
-		{
-			if cfg!(windows) {
-				let res =
-					if version::get().1 >= 1543 {
-						byondcore.find(signature!("55 8B EC 6A FF 68 ?? ?? ?? ?? 64 A1 ?? ?? ?? ?? 50 83 EC 14 53 56 57 A1 ?? ?? ?? ?? 33 C5 50 8D 45 ?? 64 A3 ?? ?? ?? ?? 8B 4D ??"))
-					} else {
-						byondcore.find(signature!("55 8B EC 6A FF 68 ?? ?? ?? ?? 64 A1 ?? ?? ?? ?? 50 83 EC 10 53 56 57 A1 ?? ?? ?? ?? 33 C5 50 8D 45 ?? 64 A3 ?? ?? ?? ?? 8B 5D ?? 0F B6 C3"))
-					};
-
-				if let Some(ptr) = res {
-					to_string = ptr as *const std::ffi::c_void;
-				}
-			}
-
-			if cfg!(unix) {
-				let res =
-					if version::get().1 >= 1543 {
-						byondcore.find(signature!("55 89 E5 83 EC 68 A1 ?? ?? ?? ?? 8B 15 ?? ?? ?? ?? 8B 0D ?? ?? ?? ?? 89 5D ??"))
-					} else {
-						byondcore.find(signature!("55 89 E5 83 EC 58 89 5D ?? 8B 5D ?? 89 75 ?? 8B 75 ?? 89 7D ?? 80 FB 54"))
-					};
-
-				if let Some(ptr) = res {
-					to_string = ptr as *const std::ffi::c_void;
-				}
-			}
-
-			if to_string.is_null() {
-				return Some("FAILED (Couldn't find to_string)".to_owned());
-			}
-		}
-
-		let mut set_variable = std::ptr::null();
-		{
-			if cfg!(windows) {
-				let res = byondcore.find(signature!("55 8B EC 8B 4D 08 0F B6 C1 48 57 8B 7D 10 83 F8 53 0F ?? ?? ?? ?? ?? 0F B6 80 ?? ?? ?? ?? FF 24 85 ?? ?? ?? ?? FF 75 18 FF 75 14 57 FF 75 0C E8 ?? ?? ?? ?? 83 C4 10 5F 5D C3"));
-
-				if let Some(ptr) = res {
-					set_variable = ptr as *const std::ffi::c_void;
-				}
-			}
+		let to_string_signature = if version::get().1 >= 1543 {
+			SIGNATURES.to_string_514
+		} else {
+			SIGNATURES.to_string_513
+		};
 
-			if cfg!(unix) {
-				let res =
-					if version::get().1 >= 1543 {
-						byondcore.find(signature!("55 89 E5 81 EC A8 00 00 00 8B 55 ?? 89 5D ?? 8B 4D ?? 89 7D ?? 8B 5D ??"))
-					} else {
-						byondcore.find(signature!("55 89 E5 81 EC A8 00 00 00 8B 55 ?? 8B 45 ?? 89 5D ?? 8B 5D ?? 89 7D ??"))
-					};
+		let to_string = match find_signature(&byondcore, "to_string", false, to_string_signature) {
+			Some(ptr) => ptr as *const c_void,
+			None => std::ptr::null(),
+		};
 
-				if let Some(ptr) = res {
-					set_variable = ptr as *const std::ffi::c_void;
-				}
-			}
+		#[cfg(windows)]
+		let set_variable_signature = SIGNATURES.set_variable;
 
-			if set_variable.is_null() {
-				return Some("FAILED (Couldn't find set_variable)".to_owned());
-			}
-		}
+		#[cfg(unix)]
+		let set_variable_signature = if version::get().1 >= 1543 {
+			SIGNATURES.set_variable_514
+		} else {
+			SIGNATURES.set_variable_513
+		};
 
-		let mut current_execution_context = std::ptr::null_mut();
-		{
-			if cfg!(windows) {
-				if let Some(ptr) = byondcore.find(signature!("A1 ?? ?? ?? ?? FF 75 ?? 89 4D ?? 8B 4D ?? 8B 00 6A 00 52 6A 12 FF 70 ??")) {
-					current_execution_context = unsafe { *((ptr.add(1)) as *mut *mut *mut raw_types::procs::ExecutionContext) };
-				}
-			}
+		let set_variable =
+			match find_signature(&byondcore, "set_variable", true, set_variable_signature) {
+				Some(ptr) => ptr as *const c_void,
+				None => return "FAILED (Couldn't find set_variable)".to_owned(),
+			};
+
+		let current_execution_context = match find_signature(
+			&byondcore,
+			"current_execution_context",
+			true,
+			SIGNATURES.current_execution_context,
+		) {
+			Some(ptr) => unsafe {
+				*((ptr.add(1)) as *mut *mut *mut raw_types::procs::ExecutionContext)
+			},
+			None => return "FAILED (Couldn't find current_execution_context)".to_owned(),
+		};
 
-			if cfg!(unix) {
-				if let Some(ptr) = byondcore.find(signature!("A1 ?? ?? ?? ?? 85 C0 0F 84 ?? ?? ?? ?? 8B 00 85 C0 0F 84 ?? ?? ?? ?? 8B 00")) {
-					current_execution_context = unsafe { *((ptr.add(1)) as *mut *mut *mut raw_types::procs::ExecutionContext) };
-				}
-			}
+		// get_string_table_entry bounds-checks its argument against the size of the string table.
+		// This is only used by the strings module, which reports nothing if it isn't found.
+		// The offset is where the pointer is in the cmp instruction
+		#[cfg(windows)]
+		let string_table_count_offset = 8;
 
-			if current_execution_context.is_null() {
-				return Some("FAILED (Couldn't find current_execution_context)".to_owned());
-			}
-		}
+		#[cfg(unix)]
+		let string_table_count_offset = 11;
+
+		let string_table_count = match find_signature(
+			&byondcore,
+			"string_table_count",
+			false,
+			SIGNATURES.string_table_count,
+		) {
+			Some(ptr) => unsafe { *(ptr.add(string_table_count_offset) as *const *const u32) },
+			None => std::ptr::null(),
+		};
 
 		unsafe {
 			raw_types::funcs::CURRENT_EXECUTION_CONTEXT = current_execution_context;
-			raw_types::funcs::SUSPENDED_PROCS = *(suspended_procs.add(1) as *mut *mut raw_types::procs::SuspendedProcs);
-			raw_types::funcs::SUSPENDED_PROCS_BUFFER = *(suspended_procs_buffer.add(2) as *mut *mut raw_types::procs::SuspendedProcsBuffer);
+			raw_types::funcs::STRING_TABLE_COUNT = string_table_count;
+			raw_types::funcs::SUSPENDED_PROCS =
+				*(suspended_procs.add(1) as *mut *mut raw_types::procs::SuspendedProcs);
+			raw_types::funcs::SUSPENDED_PROCS_BUFFER = *(suspended_procs_buffer.add(2)
+				as *mut *mut raw_types::procs::SuspendedProcsBuffer);
 			raw_types::funcs::call_proc_by_id_byond = call_proc_by_id;
 			raw_types::funcs::call_datum_proc_by_name_byond = call_datum_proc_by_name;
 			raw_types::funcs::get_proc_array_entry_byond = get_proc_array_entry;
@@ -336,46 +479,39 @@ byond_ffi_fn! { auxtools_init(_input) {
 			raw_types::funcs::runtime_byond = runtime;
 		}
 
+		strings::validate_table_count();
+
 		if pin_dll().is_err() {
-			return Some("FAILED (Could not pin the library in memory.)".to_owned());
+			return "FAILED (Could not pin the library in memory.)".to_owned();
 		}
 
 		if let Err(_) = hooks::init() {
-			return Some("Failed (Couldn't initialize proc hooking)".to_owned());
+			return "Failed (Couldn't initialize proc hooking)".to_owned();
 		}
 
 		set_init_level(InitLevel::Partial);
 	}
 
-
 	if get_init_level() == InitLevel::Partial {
 		did_partial = true;
 
 		// This is a heap ptr so fetch it on partial loads
-		let mut variable_names = std::ptr::null();
-		{
-			if cfg!(windows) {
-				if let Some(ptr) = byondcore.find(signature!("8B 1D ?? ?? ?? ?? 2B 0C ?? 8B 5D ?? 74 ?? 85 C9 79 ?? 0F B7 D0 EB ?? 83 C0 02")) {
-					variable_names = unsafe { *((ptr.add(2)) as *mut *mut VariableNameIdTable) };
-				}
-			}
+		// The offset is where the pointer is in the instruction
+		#[cfg(windows)]
+		let (variable_names_signature, offset) = (SIGNATURES.variable_names, 2);
 
-			if cfg!(unix) {
-				if version::get().1 >= 1543 {
-					if let Some(ptr) = byondcore.find(signature!("A1 ?? ?? ?? ?? 8B 13 8B 39 8B 75 ?? 8B 14 ?? 89 7D ?? 8B 3C ?? 83 EE 02")) {
-						variable_names = unsafe { *((ptr.add(1)) as *mut *mut VariableNameIdTable) };
-					}
-				} else {
-					if let Some(ptr) = byondcore.find(signature!("8B 35 ?? ?? ?? ?? 89 5D ?? 0F B7 08 89 75 ?? 66 C7 45 ?? 00 00 89 7D ??")) {
-						variable_names = unsafe { *((ptr.add(2)) as *mut *mut VariableNameIdTable) };
-					}
-				};
-			}
+		#[cfg(unix)]
+		let (variable_names_signature, offset) = if version::get().1 >= 1543 {
+			(SIGNATURES.variable_names_514, 1)
+		} else {
+			(SIGNATURES.variable_names_513, 2)
+		};
 
-			if variable_names.is_null() {
-				return Some("FAILED (Couldn't find variable_names)".to_owned());
-			}
-		}
+		let variable_names =
+			match find_signature(&byondcore, "variable_names", true, variable_names_signature) {
+				Some(ptr) => unsafe { *((ptr.add(offset)) as *mut *mut VariableNameIdTable) },
+				None => return "FAILED (Couldn't find variable_names)".to_owned(),
+			};
 
 		unsafe {
 			raw_types::funcs::VARIABLE_NAMES = variable_names;
@@ -383,39 +519,63 @@ byond_ffi_fn! { auxtools_init(_input) {
 
 		proc::populate_procs();
 
+		// Everything is hooked (and reported) before failing on the first error
+		let mut hook_error = None;
 		for cthook in inventory::iter::<hooks::CompileTimeHook> {
-			if let Err(e) = hooks::hook(cthook.proc_path, cthook.hook) {
-				return Some(format!("FAILED (Could not hook proc {}: {:?})", cthook.proc_path, e));
+			let res = hooks::hook(cthook.proc_path, cthook.hook, cthook.fn_name);
+			init_report::record_hook(cthook, res.as_ref().err());
+
+			if let Err(e) = res {
+				if hook_error.is_none() {
+					hook_error = Some(format!(
+						"FAILED (Could not hook proc {}: {:?})",
+						cthook.proc_path, e
+					));
+				}
 			}
 		}
+
+		if let Some(hook_error) = hook_error {
+			return hook_error;
+		}
 		set_init_level(InitLevel::None);
 	}
 
 	if did_partial {
 		bytecode_manager::init();
+		runtime_event::init();
 		string_intern::setup_interned_strings();
+		if let Err(e) = variable_intern::setup_interned_variables() {
+			return format!("FAILED ({})", e);
+		}
 	}
 
 	// Run user-defined initializers
 	if did_full {
-		if let Err(err) = init::run_full_init() {
-			return Some(format!("FAILED ({})", err));
+		if let Err(err) = init::run_full_init(continue_on_error) {
+			return format!("FAILED ({})", err);
 		}
 	}
 
 	if did_partial {
-		if let Err(err) = init::run_partial_init() {
-			return Some(format!("FAILED ({})", err));
+		if let Err(err) = init::run_partial_init(continue_on_error) {
+			return format!("FAILED ({})", err);
 		}
 	}
 
-	Some("SUCCESS".to_owned())
-} }
+	"SUCCESS".to_owned()
+}
 
 byond_ffi_fn! { auxtools_shutdown(_input) {
 	init::run_partial_shutdown();
 	string_intern::destroy_interned_strings();
+	variable_intern::destroy_interned_variables();
+	instruction_hooks::shutdown();
+	custom_opcodes::shutdown();
 	bytecode_manager::shutdown();
+	panics::shutdown();
+	runtime_aggregator::shutdown();
+	runtime_event::shutdown();
 
 	hooks::clear_hooks();
 	proc::clear_procs();
@@ -430,6 +590,29 @@ byond_ffi_fn! { auxtools_shutdown(_input) {
 
 #[cfg(test)]
 mod tests {
+	use super::parse_flags;
+
 	#[test]
 	fn test() {}
+
+	#[test]
+	fn flags_split_on_spaces_and_commas() {
+		assert_eq!(
+			parse_flags("report, continue_on_error  signatures=sigs.json"),
+			vec!["report", "continue_on_error", "signatures=sigs.json"]
+		);
+		assert!(parse_flags(" ,, ").is_empty());
+	}
+
+	#[test]
+	fn flags_keep_quoted_paths_together() {
+		assert_eq!(
+			parse_flags(r#"report signatures="C:\My Server\sigs, new.json",continue_on_error"#),
+			vec![
+				"report",
+				r"signatures=C:\My Server\sigs, new.json",
+				"continue_on_error"
+			]
+		);
+	}
 }
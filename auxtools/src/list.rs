@@ -2,6 +2,10 @@ use crate::*;
 use std::iter::FromIterator;
 
 /// A wrapper around [Values](struct.Value.html) that make working with lists a little easier
+///
+/// The list functions BYOND exports are optional signatures, so on some BYOND versions they may be missing.
+/// [append](#method.append), [remove](#method.remove), [len](#method.len) and collecting into a List panic if
+/// that happens. Use the `try_` versions to get an error instead.
 pub struct List {
 	value: Value,
 }
@@ -44,6 +48,11 @@ impl List {
 			data: raw_types::values::ValueData { id: 0 },
 		};
 
+		require_function(
+			unsafe { raw_types::funcs::get_assoc_element_byond },
+			"get_assoc_element",
+		)?;
+
 		// assoc funcs for everything else
 		unsafe {
 			if raw_types::funcs::get_assoc_element(&mut value, self.value.raw, index.raw) == 1 {
@@ -60,6 +69,11 @@ impl List {
 		let index = index.into();
 		let value = value.into();
 
+		require_function(
+			unsafe { raw_types::funcs::set_assoc_element_byond },
+			"set_assoc_element",
+		)?;
+
 		unsafe {
 			if raw_types::funcs::set_assoc_element(self.value.raw, index.raw, value.raw) == 1 {
 				return Ok(());
@@ -71,34 +85,83 @@ impl List {
 		}
 	}
 
+	/// Panics if `append_to_list` wasn't found during init. See [try_append](#method.try_append).
 	pub fn append<V: Into<Value>>(&self, value: V) {
+		if let Err(e) = self.try_append(value) {
+			panic!("{}", e);
+		}
+	}
+
+	pub fn try_append<V: Into<Value>>(&self, value: V) -> DMResult<()> {
 		let value = value.into();
 
+		require_function(
+			unsafe { raw_types::funcs::append_to_list_byond },
+			"append_to_list",
+		)?;
+
 		unsafe {
 			assert_eq!(
 				raw_types::funcs::append_to_list(self.value.raw, value.raw),
 				1
 			);
 		}
+
+		Ok(())
 	}
 
+	/// Panics if `remove_from_list` wasn't found during init. See [try_remove](#method.try_remove).
 	pub fn remove<V: Into<Value>>(&self, value: V) {
+		if let Err(e) = self.try_remove(value) {
+			panic!("{}", e);
+		}
+	}
+
+	pub fn try_remove<V: Into<Value>>(&self, value: V) -> DMResult<()> {
 		let value = value.into();
 
+		require_function(
+			unsafe { raw_types::funcs::remove_from_list_byond },
+			"remove_from_list",
+		)?;
+
 		unsafe {
 			assert_eq!(
 				raw_types::funcs::remove_from_list(self.value.raw, value.raw),
 				1
 			);
 		}
+
+		Ok(())
 	}
 
+	/// Panics if `get_length` wasn't found during init. See [try_len](#method.try_len).
 	pub fn len(&self) -> u32 {
+		match self.try_len() {
+			Ok(len) => len,
+			Err(e) => panic!("{}", e),
+		}
+	}
+
+	pub fn try_len(&self) -> DMResult<u32> {
+		require_function(unsafe { raw_types::funcs::get_length_byond }, "get_length")?;
+
 		let mut length: u32 = 0;
 		unsafe {
 			assert_eq!(raw_types::funcs::get_length(&mut length, self.value.raw), 1);
 		}
-		length
+		Ok(length)
+	}
+
+	/// Like collecting into a List, but fails instead of panicking if `append_to_list` wasn't found during init.
+	pub fn try_from_iter<I: IntoIterator<Item = Value>>(it: I) -> DMResult<Self> {
+		let res = Self::new();
+
+		for val in it {
+			res.try_append(val)?;
+		}
+
+		Ok(res)
 	}
 
 	pub fn is_list(value: &Value) -> bool {
@@ -126,6 +189,7 @@ impl List {
 	}
 }
 
+/// Panics if `append_to_list` wasn't found during init. See [List::try_from_iter](struct.List.html#method.try_from_iter).
 impl FromIterator<Value> for List {
 	fn from_iter<I: IntoIterator<Item = Value>>(it: I) -> Self {
 		let res = Self::new();
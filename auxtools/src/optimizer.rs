@@ -0,0 +1,169 @@
+//! A simple peephole optimizer for hot procs.
+//!
+//! Nothing is optimized unless asked for. Procs are opted in by path with [optimize_procs](fn.optimize_procs.html),
+//! and [Proc::reset_bytecode](../struct.Proc.html#method.reset_bytecode) undoes it.
+//!
+//! Only instructions that are next to each other (with no labels in between) are ever merged, so nothing a jump
+//! lands on is changed.
+//!
+//! Repeated variable lookups are deliberately left alone. Reads of `src.x` or a global can't be proven to return
+//! the same value twice, because any call in between can change it, and reads of arguments and locals already
+//! cost no more than copying the value would.
+
+use crate::*;
+use dmasm::operands::Value as Operand;
+use dmasm::{Instruction, Node};
+
+/// Which passes to run. The default runs everything.
+#[derive(Clone, Copy, Debug)]
+pub struct OptimizeOptions {
+	/// Replaces arithmetic on two constants with its result.
+	pub fold_constants: bool,
+	/// Removes `DbgFile` and `DbgLine`. Runtimes in the proc will no longer have line numbers and the debugger
+	/// can't put breakpoints in it, so this is meant for procs that are known to work.
+	pub strip_debug_info: bool,
+}
+
+impl Default for OptimizeOptions {
+	fn default() -> Self {
+		Self {
+			fold_constants: true,
+			strip_debug_info: true,
+		}
+	}
+}
+
+/// What optimizing a single proc did.
+#[derive(Clone, Debug, Default)]
+pub struct OptimizeReport {
+	/// Instructions before optimizing.
+	pub before: usize,
+	/// Instructions after optimizing.
+	pub after: usize,
+	/// Arithmetic instructions that were replaced by a constant.
+	pub folded: usize,
+	/// `DbgFile` and `DbgLine` instructions that were removed.
+	pub stripped: usize,
+}
+
+fn fold(lhs: f32, rhs: f32, op: &Instruction) -> Option<f32> {
+	match op {
+		Instruction::Add => Some(lhs + rhs),
+		Instruction::Sub => Some(lhs - rhs),
+		Instruction::Mul => Some(lhs * rhs),
+		// Leave the runtime in place
+		Instruction::Div if rhs != 0.0 => Some(lhs / rhs),
+		_ => None,
+	}
+}
+
+fn number(node: &Node) -> Option<f32> {
+	match node {
+		Node::Instruction(Instruction::PushVal(Operand::Number(x)), _) => Some(*x),
+		_ => None,
+	}
+}
+
+fn fold_constants(nodes: &mut Vec<Node>) -> usize {
+	let mut folded = 0;
+	let mut i = 0;
+
+	while i + 2 < nodes.len() {
+		let result = match (number(&nodes[i]), number(&nodes[i + 1]), &nodes[i + 2]) {
+			(Some(lhs), Some(rhs), Node::Instruction(op, _)) => fold(lhs, rhs, op),
+			_ => None,
+		};
+
+		match result {
+			Some(result) => {
+				nodes.splice(
+					i..i + 3,
+					std::iter::once(Node::Instruction(
+						Instruction::PushVal(Operand::Number(result)),
+						(),
+					)),
+				);
+				folded += 1;
+
+				// The result might be the left hand side of another constant expression
+				i = i.saturating_sub(1);
+			}
+
+			None => i += 1,
+		}
+	}
+
+	folded
+}
+
+fn strip_debug_info(nodes: &mut Vec<Node>) -> usize {
+	let len = nodes.len();
+
+	nodes.retain(|node| {
+		!matches!(
+			node,
+			Node::Instruction(Instruction::DbgFile(_), _)
+				| Node::Instruction(Instruction::DbgLine(_), _)
+		)
+	});
+
+	len - nodes.len()
+}
+
+/// Runs the optimizer over a patch without installing it anywhere.
+pub fn optimize_patch(patch: &mut BytecodePatch, options: OptimizeOptions) -> OptimizeReport {
+	let mut report = OptimizeReport {
+		before: patch.len(),
+		..Default::default()
+	};
+
+	if options.strip_debug_info {
+		report.stripped = strip_debug_info(patch.nodes_mut());
+	}
+
+	if options.fold_constants {
+		report.folded = fold_constants(patch.nodes_mut());
+	}
+
+	report.after = patch.len();
+	report
+}
+
+/// Optimizes a proc and installs the result. Always starts from the original bytecode, so it's safe to call again.
+pub fn optimize(proc: &Proc, options: OptimizeOptions) -> Result<OptimizeReport, PatchError> {
+	let mut patch = BytecodePatch::from_original(proc)?;
+	let report = optimize_patch(&mut patch, options);
+	patch.install(proc)?;
+	Ok(report)
+}
+
+/// Optimizes every proc (and every override of it) matching one of the given [glob patterns](../struct.Proc.html#method.find_glob).
+///
+/// # Examples
+/// ```ignore
+/// for (proc, report) in optimizer::optimize_procs(&["/datum/gas_mixture/proc/*"], Default::default()) {
+///     match report {
+///         Ok(report) => println!("{}: {} -> {} instructions", proc.path, report.before, report.after),
+///         Err(e) => println!("{}: {}", proc.path, e),
+///     }
+/// }
+/// ```
+pub fn optimize_procs<S: AsRef<str>>(
+	whitelist: &[S],
+	options: OptimizeOptions,
+) -> Vec<(Proc, Result<OptimizeReport, PatchError>)> {
+	let mut res = vec![];
+
+	for pattern in whitelist {
+		for base in Proc::find_glob(pattern.as_ref()) {
+			for id in 0..Proc::override_count(base.path.as_str()) {
+				if let Some(proc) = Proc::find_override(base.path.as_str(), id) {
+					let report = optimize(&proc, options);
+					res.push((proc, report));
+				}
+			}
+		}
+	}
+
+	res
+}
@@ -0,0 +1,103 @@
+//! Keeps Rust panics from unwinding into BYOND.
+//!
+//! Every place BYOND calls into auxtools (hooks, runtime handlers, instruction hooks and
+//! [byond_ffi_fn](../macro.byond_ffi_fn.html) functions) catches panics. A panic is reported like any other hook
+//! error, as a DM runtime carrying the panic message and a backtrace, and BYOND carries on. Panics in runtime
+//! handlers are written to stderr instead, as raising a runtime from one would go straight back into it.
+//!
+//! Hooks that keep panicking can be disabled automatically with [set_failure_limit](fn.set_failure_limit.html).
+
+use crate::*;
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Once;
+
+/// Something that can panic and be disabled for it.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum PanicSource {
+	ProcHook(raw_types::procs::ProcId),
+	InstructionHook(instruction_hooks::InstructionHookId),
+}
+
+thread_local! {
+	// Filled in by our panic hook, which runs before unwinding starts and is the only place a useful backtrace exists
+	static LAST_PANIC: RefCell<Option<(String, String)>> = RefCell::new(None);
+	static FAILURE_LIMIT: Cell<Option<u32>> = Cell::new(None);
+	static FAILURES: RefCell<HashMap<PanicSource, u32>> = RefCell::new(HashMap::new());
+}
+
+static INSTALL_HOOK: Once = Once::new();
+
+pub(crate) fn install_hook() {
+	INSTALL_HOOK.call_once(|| {
+		let previous = panic::take_hook();
+
+		panic::set_hook(Box::new(move |info| {
+			let location = match info.location() {
+				Some(location) => format!("{}:{}", location.file(), location.line()),
+				None => "unknown location".to_owned(),
+			};
+
+			let backtrace = Backtrace::force_capture().to_string();
+			LAST_PANIC.with(|x| x.replace(Some((location, backtrace))));
+
+			previous(info);
+		}));
+	});
+}
+
+/// Disables hooks after they've panicked `limit` times, or never with `None` (the default). A limit of 0 disables
+/// them the first time they panic, like 1.
+///
+/// Disabled proc hooks are unhooked, so the DM proc runs as if it was never hooked. Counts are kept until shutdown,
+/// so a hook that is added again after being disabled is disabled again the next time it panics.
+pub fn set_failure_limit(limit: Option<u32>) {
+	FAILURE_LIMIT.with(|x| x.set(limit));
+}
+
+fn payload_message(payload: &(dyn Any + Send)) -> String {
+	if let Some(s) = payload.downcast_ref::<&str>() {
+		(*s).to_owned()
+	} else if let Some(s) = payload.downcast_ref::<String>() {
+		s.clone()
+	} else {
+		"Box<dyn Any>".to_owned()
+	}
+}
+
+/// Runs `f`, turning a panic into a [Runtime](../struct.Runtime.html) with the message, location and backtrace.
+pub(crate) fn catch<R, F: FnOnce() -> R>(f: F) -> Result<R, Runtime> {
+	panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| {
+		let message = payload_message(&*payload);
+
+		match LAST_PANIC.with(|x| x.borrow_mut().take()) {
+			Some((location, backtrace)) => Runtime::new(format!(
+				"Rust panic at {}: {}\n{}",
+				location, message, backtrace
+			)),
+			None => Runtime::new(format!("Rust panic: {}", message)),
+		}
+	})
+}
+
+/// Counts a panic against `source`. Returns true if it has reached the limit and should be disabled.
+pub(crate) fn record_failure(source: PanicSource) -> bool {
+	let limit = match FAILURE_LIMIT.with(|x| x.get()) {
+		Some(limit) => limit,
+		None => return false,
+	};
+
+	FAILURES.with(|failures| {
+		let mut failures = failures.borrow_mut();
+		let count = failures.entry(source).or_insert(0);
+		*count += 1;
+		*count >= limit
+	})
+}
+
+pub(crate) fn shutdown() {
+	FAILURES.with(|x| x.borrow_mut().clear());
+}
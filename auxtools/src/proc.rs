@@ -2,6 +2,7 @@ use crate::*;
 use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fmt;
 
 //
@@ -27,6 +28,60 @@ use std::fmt;
 //
 //
 
+/// Settings a proc was compiled with, such as `set hidden = 1`.
+///
+/// BYOND packs these into a single word: the `set src in` range, then the `set src` mode, then a byte of flags.
+/// BYOND doesn't document this layout and it was worked out by hand, so it may be wrong for some builds.
+/// auxtest's `test_proc_flags` checks it against procs with known `set` settings when run under DreamDaemon.
+/// Prefer [Proc::is_verb](struct.Proc.html#method.is_verb) and friends where they're enough.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ProcFlags(u32);
+
+impl ProcFlags {
+	const HIDDEN: u32 = 0x01;
+	const NO_POPUP_MENU: u32 = 0x02;
+	const INSTANT: u32 = 0x04;
+	const BACKGROUND: u32 = 0x10;
+
+	pub fn bits(&self) -> u32 {
+		self.0
+	}
+
+	fn settings(&self) -> u32 {
+		(self.0 >> 16) & 0xFF
+	}
+
+	/// `set hidden = 1`
+	pub fn hidden(&self) -> bool {
+		self.settings() & Self::HIDDEN != 0
+	}
+
+	/// `set popup_menu = 0` turns this off
+	pub fn popup_menu(&self) -> bool {
+		self.settings() & Self::NO_POPUP_MENU == 0
+	}
+
+	/// `set instant = 1`
+	pub fn instant(&self) -> bool {
+		self.settings() & Self::INSTANT != 0
+	}
+
+	/// `set background = 1`
+	pub fn background(&self) -> bool {
+		self.settings() & Self::BACKGROUND != 0
+	}
+
+	/// The N in `set src in view(N)`
+	pub fn src_range(&self) -> u8 {
+		(self.0 & 0xFF) as u8
+	}
+
+	/// The raw `set src` mode (usr, view, oview, world, etc.)
+	pub fn src_access(&self) -> u8 {
+		((self.0 >> 8) & 0xFF) as u8
+	}
+}
+
 /// Used to hook and call procs.
 #[derive(Clone)]
 pub struct Proc {
@@ -65,6 +120,52 @@ impl Proc {
 		})
 	}
 
+	fn optional_string(id: raw_types::strings::StringId) -> Option<StringRef> {
+		if !id.valid() {
+			return None;
+		}
+
+		Some(unsafe { StringRef::from_id(id) })
+	}
+
+	/// The name shown to players for verbs (`set name`). Defaults to the last part of the path.
+	pub fn name(&self) -> Option<StringRef> {
+		Self::optional_string(unsafe { (*self.entry).name })
+	}
+
+	/// `set desc`
+	pub fn desc(&self) -> Option<StringRef> {
+		Self::optional_string(unsafe { (*self.entry).desc })
+	}
+
+	/// The verb panel this proc shows up in (`set category`).
+	pub fn category(&self) -> Option<StringRef> {
+		Self::optional_string(unsafe { (*self.entry).category })
+	}
+
+	pub fn flags(&self) -> ProcFlags {
+		ProcFlags(unsafe { (*self.entry).flags })
+	}
+
+	/// Whether this was declared under a `verb/` block rather than `proc/`.
+	pub fn is_verb(&self) -> bool {
+		// self.path has had this stripped out, so go back to BYOND's copy
+		let path: String = unsafe { StringRef::from_id((*self.entry).path).into() };
+		path.contains("/verb/")
+	}
+
+	/// Whether the proc is hidden from the verb panels: either `set hidden = 1` or a name starting with a period.
+	pub fn is_hidden(&self) -> bool {
+		if self.flags().hidden() {
+			return true;
+		}
+
+		match self.name() {
+			Some(name) => name.data().starts_with(b"."),
+			None => false,
+		}
+	}
+
 	pub fn parameter_names(&self) -> Vec<StringRef> {
 		unsafe {
 			let (data, count) = raw_types::misc::get_parameters((*self.entry).parameters);
@@ -83,10 +184,26 @@ impl Proc {
 		}
 	}
 
+	/// Replaces the bytecode this proc runs. Any [instruction hooks](instruction_hooks/index.html) on it are removed.
 	pub fn set_bytecode(&self, bytecode: Vec<u32>) {
 		crate::bytecode_manager::set_bytecode(self, bytecode);
 	}
 
+	/// Restores the bytecode this proc was compiled with, undoing any [set_bytecode](#method.set_bytecode) calls.
+	/// Like set_bytecode, this removes any instruction hooks on the replacement.
+	pub fn reset_bytecode(&self) {
+		crate::bytecode_manager::reset_bytecode(self);
+	}
+
+	/// The bytecode this proc was compiled with, even if it has since been replaced.
+	///
+	/// This can contain instruction hooks. Use [instruction_hooks::unhooked_original_bytecode](instruction_hooks/fn.unhooked_original_bytecode.html)
+	/// to disassemble it.
+	pub unsafe fn original_bytecode(&self) -> &[u32] {
+		let (ptr, count) = crate::bytecode_manager::get_original_bytecode(self);
+		std::slice::from_raw_parts(ptr, count as usize)
+	}
+
 	pub unsafe fn bytecode_mut_ptr(&self) -> (*mut u32, u16) {
 		raw_types::misc::get_bytecode((*self.entry).bytecode)
 	}
@@ -139,7 +256,19 @@ impl Proc {
 			}
 		}
 
-		Err(runtime!("External proc call failed"))
+		Err(
+			runtime::Runtime::new(format!("call to {} failed", self.path))
+				.with_proc(self.path.clone())
+				.at_current_location(),
+		)
+	}
+
+	/// Like [call](#method.call), but fails with the first DM runtime thrown during the call, including `CRASH()`.
+	///
+	/// The return value is thrown away if anything runtimed, even if DM code caught it. See
+	/// [catch_runtimes](fn.catch_runtimes.html) for which runtimes count.
+	pub fn call_checked(&self, args: &[&Value]) -> runtime::DMResult {
+		runtime::catch_runtimes(|| self.call(args))
 	}
 
 	pub fn override_id(&self) -> u32 {
@@ -148,6 +277,79 @@ impl Proc {
 			None => 0,
 		})
 	}
+
+	/// Every proc in the world, including overrides, ordered by id.
+	pub fn all() -> Vec<Self> {
+		let mut procs: Vec<Proc> =
+			PROCS_BY_NAME.with(|h| h.borrow().iter().flat_map(|x| x.value().clone()).collect());
+
+		procs.sort_by_key(|x| x.id.0);
+		procs
+	}
+
+	/// Finds the base definition of every proc whose path starts with `prefix`.
+	pub fn find_prefix<S: Into<String>>(prefix: S) -> Vec<Self> {
+		let prefix = strip_path(prefix.into());
+		find_base_procs(|path| path.starts_with(&prefix))
+	}
+
+	/// Finds the base definition of every proc whose path matches a glob pattern.
+	///
+	/// `*` matches anything within a single path segment, `**` matches across segments and `?` matches one character.
+	///
+	/// # Examples
+	/// ```ignore
+	/// // Finds /mob/living/Life, /mob/dead/Life, etc.
+	/// let procs = Proc::find_glob("/mob/*/Life");
+	/// ```
+	pub fn find_glob<S: Into<String>>(pattern: S) -> Vec<Self> {
+		let pattern = strip_path(pattern.into());
+		find_base_procs(|path| glob_matches(pattern.as_bytes(), path.as_bytes()))
+	}
+
+	/// Finds the procs defined on a type, like `/mob/living`. Global procs live on `/`.
+	///
+	/// With `inherited` set, procs from parent types are included too, unless the type (or a closer parent) redefines them.
+	/// Parents are worked out from the type path, along with the built-in hierarchy (`/mob` -> `/atom/movable` -> `/atom` -> `/datum`).
+	pub fn defined_on<S: Into<String>>(type_path: S, inherited: bool) -> Vec<Self> {
+		let type_path = strip_path(type_path.into());
+		let mut current = Some(type_path.trim_end_matches('/').to_owned());
+		let mut seen_names = std::collections::HashSet::new();
+		let mut res = vec![];
+
+		while let Some(type_path) = current {
+			let mut procs = find_base_procs(|path| proc_type(path) == type_path);
+			procs.retain(|x| seen_names.insert(proc_name(&x.path).to_owned()));
+			res.extend(procs);
+
+			if !inherited {
+				break;
+			}
+
+			current = parent_type(&type_path).map(|x| x.to_owned());
+		}
+
+		res
+	}
+
+	/// How many times the proc at `path` is defined. Returns 0 if it doesn't exist.
+	pub fn override_count<S: Into<String>>(path: S) -> u32 {
+		let path = strip_path(path.into());
+		PROCS_BY_NAME.with(|h| match h.borrow().get(&path) {
+			Some(procs) => procs.len() as u32,
+			None => 0,
+		})
+	}
+
+	/// The number of definitions of every proc path in the world.
+	pub fn override_counts() -> HashMap<String, u32> {
+		PROCS_BY_NAME.with(|h| {
+			h.borrow()
+				.iter()
+				.map(|x| (x.key().clone(), x.value().len() as u32))
+				.collect()
+		})
+	}
 }
 
 impl fmt::Debug for Proc {
@@ -160,10 +362,87 @@ impl fmt::Debug for Proc {
 thread_local!(static PROCS_BY_NAME: RefCell<DashMap<String, Vec<Proc>>> = RefCell::new(DashMap::new()));
 thread_local!(static PROC_OVERRIDE_IDS: RefCell<DashMap<raw_types::procs::ProcId, u32>> = RefCell::new(DashMap::new()));
 
-fn strip_path(p: String) -> String {
+pub(crate) fn strip_path(p: String) -> String {
 	p.replace("/proc/", "/").replace("/verb/", "/")
 }
 
+// Paths are stored stripped, so `/mob/proc/Life` is `/mob/Life` and belongs to `/mob`
+pub(crate) fn proc_type(path: &str) -> &str {
+	match path.rfind('/') {
+		Some(idx) => &path[..idx],
+		None => "",
+	}
+}
+
+pub(crate) fn proc_name(path: &str) -> &str {
+	match path.rfind('/') {
+		Some(idx) => &path[idx + 1..],
+		None => path,
+	}
+}
+
+pub(crate) fn parent_type(type_path: &str) -> Option<&str> {
+	match type_path {
+		// Global procs and types that aren't datums don't inherit anything
+		"" | "/datum" | "/client" | "/world" | "/list" | "/savefile" => None,
+		"/atom" => Some("/datum"),
+		"/atom/movable" | "/area" | "/turf" => Some("/atom"),
+		"/obj" | "/mob" => Some("/atom/movable"),
+		_ => match type_path.rfind('/') {
+			Some(0) | None => Some("/datum"),
+			Some(idx) => Some(&type_path[..idx]),
+		},
+	}
+}
+
+fn glob_matches(pattern: &[u8], path: &[u8]) -> bool {
+	match pattern.split_first() {
+		None => path.is_empty(),
+
+		Some((b'*', rest)) => {
+			if let Some((b'*', rest)) = rest.split_first() {
+				return (0..=path.len()).any(|i| glob_matches(rest, &path[i..]));
+			}
+
+			for i in 0..=path.len() {
+				if glob_matches(rest, &path[i..]) {
+					return true;
+				}
+
+				// A single * doesn't cross path segments
+				if path.get(i) == Some(&b'/') {
+					break;
+				}
+			}
+
+			false
+		}
+
+		Some((b'?', rest)) => match path.split_first() {
+			Some((c, path)) if *c != b'/' => glob_matches(rest, path),
+			_ => false,
+		},
+
+		Some((c, rest)) => match path.split_first() {
+			Some((d, path)) if c == d => glob_matches(rest, path),
+			_ => false,
+		},
+	}
+}
+
+fn find_base_procs<F: Fn(&str) -> bool>(filter: F) -> Vec<Proc> {
+	let mut procs: Vec<Proc> = PROCS_BY_NAME.with(|h| {
+		h.borrow()
+			.iter()
+			.filter(|x| filter(x.key()))
+			.filter_map(|x| x.value().first().cloned())
+			.collect()
+	});
+
+	procs.sort_by_key(|x| x.id.0);
+	procs
+}
+
 pub fn populate_procs() {
 	let mut i: u32 = 0;
 	loop {
@@ -14,6 +14,7 @@ pub static mut SUSPENDED_PROCS_BUFFER: *mut procs::SuspendedProcsBuffer = std::p
 pub static mut SUSPENDED_PROCS: *mut procs::SuspendedProcs = std::ptr::null_mut();
 
 pub static mut VARIABLE_NAMES: *const variables::VariableNameIdTable = std::ptr::null();
+pub static mut STRING_TABLE_COUNT: *const u32 = std::ptr::null();
 
 // Function pointers exported by C++ but set by Rust
 // Rust shouldn't call these so we're going to treat them as void ptrs for simplicity
@@ -182,6 +182,31 @@ pub fn get_locals(id: LocalsId) -> (*const strings::VariableId, usize) {
 	unsafe { ((*misc).locals.names, (*misc).locals.count as usize) }
 }
 
+pub fn set_locals(id: LocalsId, new_names: *const strings::VariableId, new_count: u16) {
+	let mut misc: *mut c_void = std::ptr::null_mut();
+	unsafe {
+		assert_eq!(super::funcs::get_misc_by_id(&mut misc, id.as_misc_id()), 1);
+	}
+
+	let (major, minor) = version::get();
+
+	// Lame
+	if major > 513 || minor >= 1539 {
+		let misc = misc as *mut Misc_V2;
+		unsafe {
+			(*misc).locals.names = new_names;
+			(*misc).locals.count = new_count;
+		}
+		return;
+	}
+
+	let misc = misc as *mut Misc_V1;
+	unsafe {
+		(*misc).locals.names = new_names;
+		(*misc).locals.count = new_count;
+	}
+}
+
 pub fn get_parameters(id: ParametersId) -> (*const ParametersData, usize) {
 	let mut misc: *mut c_void = std::ptr::null_mut();
 	unsafe {
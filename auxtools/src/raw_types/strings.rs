@@ -11,7 +11,7 @@ impl StringId {
 }
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct VariableId(pub u32);
 
 #[repr(C)]
@@ -1,19 +1,217 @@
+use crate::raw_types;
+use crate::string::StringRef;
 use crate::value::Value;
+use crate::Proc;
+use std::cell::RefCell;
+use std::fmt;
 use std::result;
 
-/// Represents a byond runtime, sort of. This will probably drastically in the future.
+/// Represents a byond runtime, sort of.
 ///
-/// These are just simple error messages that our API and hooks can return as failure states.
+/// These are error messages that our API and hooks can return as failure states. Besides the message they can say
+/// which proc failed, where DM was when it happened, what DM itself had to say and what caused it. Everything but
+/// the message is optional and filled in by whoever knows it.
+///
+/// The [Display](https://doc.rust-lang.org/std/fmt/trait.Display.html) output includes everything but the source,
+/// which is available through [Error::source](https://doc.rust-lang.org/std/error/trait.Error.html#method.source).
 #[derive(Debug)]
 pub struct Runtime {
 	pub message: String,
+	proc: Option<String>,
+	file: Option<String>,
+	line: Option<u32>,
+	dm_message: Option<String>,
+	source: Option<Box<dyn std::error::Error + Send + Sync>>,
 }
 
 impl Runtime {
 	pub fn new<S: Into<String>>(message: S) -> Self {
 		Self {
 			message: message.into(),
+			proc: None,
+			file: None,
+			line: None,
+			dm_message: None,
+			source: None,
+		}
+	}
+
+	/// Path of the proc that failed.
+	pub fn proc(&self) -> Option<&str> {
+		self.proc.as_deref()
+	}
+
+	/// The file DM was executing when this happened.
+	pub fn file(&self) -> Option<&str> {
+		self.file.as_deref()
+	}
+
+	/// The line DM was executing when this happened.
+	pub fn line(&self) -> Option<u32> {
+		self.line
+	}
+
+	/// The text of the runtime DM threw, if it threw one.
+	pub fn dm_message(&self) -> Option<&str> {
+		self.dm_message.as_deref()
+	}
+
+	pub fn with_proc<S: Into<String>>(mut self, proc: S) -> Self {
+		self.proc = Some(proc.into());
+		self
+	}
+
+	pub fn with_location<S: Into<String>>(mut self, file: S, line: u32) -> Self {
+		self.file = Some(file.into());
+		self.line = Some(line);
+		self
+	}
+
+	pub fn with_dm_message<S: Into<String>>(mut self, dm_message: S) -> Self {
+		self.dm_message = Some(dm_message.into());
+		self
+	}
+
+	pub fn with_source<E: std::error::Error + Send + Sync + 'static>(mut self, source: E) -> Self {
+		self.source = Some(Box::new(source));
+		self
+	}
+
+	/// Fills in the file and line from an execution context, plus the proc if it isn't known yet.
+	///
+	/// # Safety
+	/// The context has to be valid or null.
+	pub unsafe fn with_context(mut self, ctx: *const raw_types::procs::ExecutionContext) -> Self {
+		if ctx.is_null() {
+			return self;
+		}
+
+		if self.proc.is_none() {
+			let instance = (*ctx).proc_instance;
+			if !instance.is_null() {
+				self.proc = Proc::from_id((*instance).proc).map(|x| x.path);
+			}
+		}
+
+		if (*ctx).filename.valid() {
+			self.file = Some(StringRef::from_id((*ctx).filename).to_string());
+			self.line = Some((*ctx).line);
+		}
+
+		self
+	}
+
+	/// Fills in the file and line from whatever DM is currently executing, plus the proc if it isn't known yet.
+	pub fn at_current_location(self) -> Self {
+		unsafe {
+			if raw_types::funcs::CURRENT_EXECUTION_CONTEXT.is_null() {
+				return self;
+			}
+
+			self.with_context(*raw_types::funcs::CURRENT_EXECUTION_CONTEXT)
+		}
+	}
+
+	/// Reads a DM `/exception`, like the one a `try`/`catch` block gets.
+	///
+	/// The exception's `name` becomes the message and its `desc` (where BYOND puts the details) the DM message.
+	/// The proc that [to_exception](#method.to_exception) adds to the name is split back out.
+	pub fn from_exception(exception: &Value) -> Self {
+		let name = exception
+			.get_string(crate::byond_string!("name"))
+			.unwrap_or_else(|_| "unknown exception".to_owned());
+
+		let mut runtime = match split_proc(&name) {
+			Some((message, proc)) => Self::new(message).with_proc(proc),
+			None => Self::new(name),
+		};
+
+		if let Ok(desc) = exception.get_string(crate::byond_string!("desc")) {
+			if !desc.is_empty() {
+				runtime.dm_message = Some(desc);
+			}
+		}
+
+		if let (Ok(file), Ok(line)) = (
+			exception.get_string(crate::byond_string!("file")),
+			exception.get_number(crate::byond_string!("line")),
+		) {
+			runtime = runtime.with_location(file, line as u32);
+		}
+
+		runtime
+	}
+
+	/// Creates a DM `/exception` for this runtime, suitable for `throw`ing.
+	///
+	/// auxtools can't create datums itself, so this needs the following proc in your DM code:
+	/// ```dm
+	/// /proc/auxtools_new_exception(name, file, line)
+	///     return new /exception(name, file, line)
+	/// ```
+	pub fn to_exception(&self) -> DMResult {
+		let constructor = Proc::find("/proc/auxtools_new_exception")
+			.ok_or_else(|| Runtime::new("/proc/auxtools_new_exception is not defined"))?;
+
+		let name = match &self.proc {
+			Some(proc) => format!("{} (in {})", self.message, proc),
+			None => self.message.clone(),
+		};
+
+		let file = match &self.file {
+			Some(file) => Value::from_string(file)?,
+			None => Value::null(),
+		};
+
+		let line = match self.line {
+			Some(line) => Value::from(line as f32),
+			None => Value::null(),
+		};
+
+		let exception = constructor.call(&[&Value::from_string(name)?, &file, &line])?;
+
+		if let Some(dm_message) = &self.dm_message {
+			exception.set(
+				crate::byond_string!("desc"),
+				Value::from_string(dm_message)?,
+			)?;
+		}
+
+		Ok(exception)
+	}
+}
+
+// Splits "message (in /proc/path)" into its parts
+fn split_proc(name: &str) -> Option<(&str, &str)> {
+	let rest = name.strip_suffix(')')?;
+	let idx = rest.rfind(" (in ")?;
+	Some((&rest[..idx], &rest[idx + " (in ".len()..]))
+}
+
+impl fmt::Display for Runtime {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.message)?;
+
+		if let Some(dm_message) = &self.dm_message {
+			if dm_message != &self.message {
+				write!(f, ": {}", dm_message)?;
+			}
 		}
+
+		match (&self.proc, &self.file, self.line) {
+			(Some(proc), Some(file), Some(line)) => write!(f, " ({}, {}:{})", proc, file, line),
+			(Some(proc), _, _) => write!(f, " ({})", proc),
+			(None, Some(file), Some(line)) => write!(f, " ({}:{})", file, line),
+			_ => Ok(()),
+		}
+	}
+}
+
+impl std::error::Error for Runtime {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		self.source
+			.as_deref()
+			.map(|source| source as &(dyn std::error::Error + 'static))
 	}
 }
 
@@ -29,3 +227,115 @@ macro_rules! runtime {
 }
 
 pub type DMResult<T = Value> = result::Result<T, Runtime>;
+
+thread_local! {
+	// One list per active catch scope, innermost last
+	static CAUGHT_RUNTIMES: RefCell<Vec<Vec<Runtime>>> = RefCell::new(vec![]);
+}
+
+// Pops the scope even if the closure panics
+struct CatchScope;
+
+impl CatchScope {
+	fn new() -> Self {
+		CAUGHT_RUNTIMES.with(|x| x.borrow_mut().push(vec![]));
+		Self
+	}
+}
+
+impl Drop for CatchScope {
+	fn drop(&mut self) {
+		CAUGHT_RUNTIMES.with(|x| x.borrow_mut().pop());
+	}
+}
+
+/// Runs `f` and returns every DM runtime thrown while it ran, in order.
+///
+/// Runtimes still go through BYOND's usual handling (and [runtime_handler](attr.runtime_handler.html)s) as well.
+/// Scopes can be nested, in which case only the innermost one sees a runtime.
+pub fn collect_runtimes<R, F: FnOnce() -> R>(f: F) -> (R, Vec<Runtime>) {
+	let _scope = CatchScope::new();
+	let res = f();
+	let runtimes = CAUGHT_RUNTIMES.with(|x| std::mem::take(x.borrow_mut().last_mut().unwrap()));
+	(res, runtimes)
+}
+
+/// Runs `f`, failing with the first DM runtime thrown while it ran.
+///
+/// This covers runtimes in any DM code `f` ends up running, including procs those procs call and `CRASH()`. A DM
+/// proc that runtimes stops there, but whatever called it carries on, so `f` still runs to the end.
+/// Code that runs after a proc sleeps isn't covered, because it runs after `f` has returned.
+///
+/// # Examples
+/// ```ignore
+/// let res = catch_runtimes(|| {
+///     src.call("process", &[])?;
+///     src.call("update_icon", &[])
+/// });
+///
+/// if let Err(e) = res {
+///     // e.dm_message is what BYOND had to say about it
+/// }
+/// ```
+pub fn catch_runtimes<T, F: FnOnce() -> DMResult<T>>(f: F) -> DMResult<T> {
+	let (res, mut runtimes) = collect_runtimes(f);
+
+	if runtimes.is_empty() {
+		res
+	} else {
+		Err(runtimes.remove(0))
+	}
+}
+
+// Hook errors go through `/proc/auxtools_stack_trace`, which is expected to CRASH with the message
+pub(crate) fn report_runtime(e: &Runtime) {
+	report_runtime_in(e, None);
+}
+
+// Like report_runtime, but says which Rust function it came from. The whole source chain is included.
+pub(crate) fn report_runtime_in(e: &Runtime, fn_name: Option<&str>) {
+	let mut message = match fn_name {
+		Some(fn_name) => format!("{}: {}", fn_name, e),
+		None => e.to_string(),
+	};
+
+	let mut source = std::error::Error::source(e);
+	while let Some(cause) = source {
+		message.push_str(&format!("\ncaused by: {}", cause));
+		source = cause.source();
+	}
+
+	let res = crate::panics::catch(|| match Proc::find("/proc/auxtools_stack_trace") {
+		Some(proc) => {
+			// The runtime it throws is seen by the innermost catch scope, which is how call_checked reports hook errors
+			let _ = proc.call(&[&Value::from_string(&message)?]);
+			Ok(())
+		}
+		None => Err(Runtime::new("/proc/auxtools_stack_trace is not defined")),
+	});
+
+	match res {
+		Ok(Ok(())) => {}
+		Ok(Err(report_error)) => eprintln!("auxtools: {} ({})", message, report_error),
+		Err(report_panic) => eprintln!("auxtools: {} ({})", message, report_panic),
+	}
+}
+
+// Called for every runtime BYOND throws that we aren't swallowing
+pub(crate) fn on_dm_runtime(message: &str) {
+	let catching = CAUGHT_RUNTIMES.with(|x| !x.borrow().is_empty());
+	if !catching {
+		return;
+	}
+
+	// The context that runtimed is still the current one
+	let runtime = Runtime::new(message)
+		.with_dm_message(message)
+		.at_current_location();
+
+	CAUGHT_RUNTIMES.with(|x| {
+		if let Some(scope) = x.borrow_mut().last_mut() {
+			scope.push(runtime);
+		}
+	});
+}
@@ -0,0 +1,255 @@
+//! Groups identical runtimes together and stops them flooding the logs.
+//!
+//! Runtimes are grouped by their message and where they happened (proc, file and line). Each group keeps a count
+//! and when it was first and last seen. Once a group has been logged `log_limit` times in the current window,
+//! further occurrences are kept out of the logs with [RuntimeEvent::suppress_logging](../struct.RuntimeEvent.html#method.suppress_logging)
+//! until the window is over. They're still counted.
+//!
+//! Messages often contain refs and numbers, so there can be a lot of groups. Only `max_groups` are kept, and the
+//! one that was seen longest ago is forgotten to make room for a new one.
+//!
+//! Nothing happens until [enable](fn.enable.html) is called, and everything is forgotten on shutdown.
+//!
+//! # Examples
+//! ```ignore
+//! #[init(partial)]
+//! fn setup_runtimes() -> Result<(), String> {
+//!     runtime_aggregator::enable(Default::default());
+//!     Ok(())
+//! }
+//!
+//! #[hook("/proc/dump_runtimes")]
+//! fn dump_runtimes() {
+//!     Value::from_string(runtime_aggregator::to_json())
+//! }
+//! ```
+
+use crate::*;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Copy, Debug)]
+pub struct AggregatorOptions {
+	/// How many times each runtime is logged per window.
+	pub log_limit: u32,
+	pub window: Duration,
+	/// How many groups are kept before the least recently seen ones are forgotten.
+	pub max_groups: usize,
+}
+
+impl Default for AggregatorOptions {
+	fn default() -> Self {
+		Self {
+			log_limit: 5,
+			window: Duration::from_secs(60),
+			max_groups: 1000,
+		}
+	}
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Key {
+	message: String,
+	proc: Option<String>,
+	file: Option<String>,
+	line: Option<u32>,
+}
+
+/// A group of identical runtimes.
+#[derive(Clone, Debug, Serialize)]
+pub struct RuntimeGroup {
+	pub message: String,
+	pub proc: Option<String>,
+	pub file: Option<String>,
+	pub line: Option<u32>,
+	/// How many times it has happened in total.
+	pub count: u64,
+	/// How many of those were kept out of the logs.
+	pub suppressed: u64,
+	/// Seconds since the Unix epoch.
+	pub first_seen: u64,
+	/// Seconds since the Unix epoch.
+	pub last_seen: u64,
+}
+
+struct Group {
+	info: RuntimeGroup,
+	window_start: Instant,
+	logged_in_window: u32,
+	// When it was last seen, in runtimes since the aggregator was enabled
+	last_seen_seq: u64,
+}
+
+struct State {
+	options: AggregatorOptions,
+	groups: HashMap<Key, Group>,
+	// Every group's key by its last_seen_seq, so the least recently seen is first
+	by_seq: BTreeMap<u64, Key>,
+	seq: u64,
+}
+
+impl State {
+	// Makes room for one more group
+	fn evict(&mut self) {
+		while self.groups.len() >= self.options.max_groups {
+			let oldest = match self.by_seq.keys().next() {
+				Some(seq) => *seq,
+				None => break,
+			};
+
+			if let Some(key) = self.by_seq.remove(&oldest) {
+				self.groups.remove(&key);
+			}
+		}
+	}
+}
+
+thread_local! {
+	static STATE: RefCell<Option<State>> = RefCell::new(None);
+}
+
+fn unix_time() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|x| x.as_secs())
+		.unwrap_or(0)
+}
+
+/// Starts grouping runtimes. Calling it again changes the options but keeps the groups, apart from any over the new
+/// `max_groups`, which are forgotten the next time a new group is made.
+pub fn enable(options: AggregatorOptions) {
+	STATE.with(|state| {
+		let mut state = state.borrow_mut();
+		match &mut *state {
+			Some(state) => state.options = options,
+			None => {
+				*state = Some(State {
+					options,
+					groups: HashMap::new(),
+					by_seq: BTreeMap::new(),
+					seq: 0,
+				})
+			}
+		}
+	});
+}
+
+/// Stops grouping runtimes and forgets the groups.
+pub fn disable() {
+	STATE.with(|state| state.replace(None));
+}
+
+pub fn is_enabled() -> bool {
+	STATE.with(|state| state.borrow().is_some())
+}
+
+/// Forgets the groups but keeps going.
+pub fn clear() {
+	STATE.with(|state| {
+		if let Some(state) = &mut *state.borrow_mut() {
+			state.groups.clear();
+			state.by_seq.clear();
+		}
+	});
+}
+
+/// Every group, most frequent first.
+pub fn groups() -> Vec<RuntimeGroup> {
+	let mut groups: Vec<RuntimeGroup> = STATE.with(|state| match &*state.borrow() {
+		Some(state) => state.groups.values().map(|x| x.info.clone()).collect(),
+		None => vec![],
+	});
+
+	groups.sort_by(|a, b| {
+		b.count
+			.cmp(&a.count)
+			.then_with(|| a.message.cmp(&b.message))
+	});
+	groups
+}
+
+/// Every group as a JSON array, most frequent first.
+pub fn to_json() -> String {
+	serde_json::to_string_pretty(&groups()).unwrap()
+}
+
+pub(crate) fn on_runtime(event: &RuntimeEvent) {
+	let enabled = STATE.with(|state| state.borrow().is_some());
+	if !enabled {
+		return;
+	}
+
+	let key = Key {
+		message: event.message().to_owned(),
+		proc: event.proc().map(|x| x.path),
+		file: event.file(),
+		line: event.line(),
+	};
+
+	let now = Instant::now();
+	let timestamp = unix_time();
+
+	let suppress = STATE.with(|state| {
+		let mut state = state.borrow_mut();
+		let state = match &mut *state {
+			Some(state) => state,
+			None => return false,
+		};
+
+		state.seq += 1;
+		let seq = state.seq;
+
+		if !state.groups.contains_key(&key) {
+			state.evict();
+		}
+
+		let options = state.options;
+		let group = state.groups.entry(key.clone()).or_insert_with(|| {
+			let key = key.clone();
+			Group {
+				info: RuntimeGroup {
+					message: key.message,
+					proc: key.proc,
+					file: key.file,
+					line: key.line,
+					count: 0,
+					suppressed: 0,
+					first_seen: timestamp,
+					last_seen: timestamp,
+				},
+				window_start: now,
+				logged_in_window: 0,
+				last_seen_seq: seq,
+			}
+		});
+
+		group.info.count += 1;
+		state.by_seq.remove(&group.last_seen_seq);
+		state.by_seq.insert(seq, key);
+		group.last_seen_seq = seq;
+		group.info.last_seen = timestamp;
+
+		if now.duration_since(group.window_start) >= options.window {
+			group.window_start = now;
+			group.logged_in_window = 0;
+		}
+
+		if group.logged_in_window < options.log_limit {
+			group.logged_in_window += 1;
+			false
+		} else {
+			group.info.suppressed += 1;
+			true
+		}
+	});
+
+	if suppress {
+		event.suppress_logging();
+	}
+}
+
+pub(crate) fn shutdown() {
+	disable();
+}
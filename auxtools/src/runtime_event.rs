@@ -0,0 +1,272 @@
+use crate::*;
+use std::cell::{Cell, Ref, RefCell};
+
+/// A DM runtime, as seen by [runtime_handler](attr.runtime_handler.html)s.
+///
+/// Everything is read from the context that runtimed when asked for, so handlers only pay for what they use.
+///
+/// # Examples
+/// ```ignore
+/// #[runtime_handler]
+/// fn report(event: &RuntimeEvent) {
+///     let frames = event.call_stacks().active.len();
+///     log::error!("{} ({} frames deep)", event.to_runtime(), frames);
+///     event.suppress_logging();
+/// }
+/// ```
+pub struct RuntimeEvent<'a> {
+	message: &'a str,
+	ctx: *mut raw_types::procs::ExecutionContext,
+	stacks: RefCell<Option<debug::CallStacks>>,
+	suppressed: Cell<bool>,
+}
+
+// BYOND logs a runtime to world.log itself unless world/Error is overridden, in which case the override is called
+// instead. While a suppressed runtime is being handled, world.log points at the null device and the world/Error
+// call is skipped.
+struct Suppression {
+	world_error: Option<raw_types::procs::ProcId>,
+	null_log: Option<Value>,
+	// What world.log was before the suppressed runtime, if one is being handled
+	saved_log: Option<Value>,
+}
+
+static mut SUPPRESSION: Option<Suppression> = None;
+
+// The world/Error call to skip. Checked on every proc call, so it's kept apart from everything else.
+static mut SKIPPED_WORLD_ERROR: Option<raw_types::procs::ProcId> = None;
+
+// A file on the null device, made by calling DM's file() through the expression stub like AssembleEnv::get_type
+fn null_log() -> Option<Value> {
+	let path = Value::from_string(if cfg!(windows) { "nul" } else { "/dev/null" }).ok()?;
+	let expr = dmasm::compiler::compile_expr("file(path)", &["path"]).ok()?;
+	let assembly = dmasm::assembler::assemble(&expr, &mut AssembleEnv).ok()?;
+
+	let proc = Proc::find("/proc/auxtools_expr_stub")?;
+	proc.set_bytecode(assembly);
+
+	let res = proc.call(&[&path]).ok()?.as_list().ok()?.get(1).ok()?;
+	if res == Value::null() {
+		return None;
+	}
+
+	Some(res)
+}
+
+pub(crate) fn init() {
+	// BYOND calls the last override
+	let mut world_error = None;
+	let mut override_id = 0;
+	while let Some(proc) = Proc::find_override("/world/Error", override_id) {
+		world_error = Some(proc.id);
+		override_id += 1;
+	}
+
+	// Only needed when BYOND does the logging
+	let null_log = match world_error {
+		Some(_) => None,
+		None => null_log(),
+	};
+
+	unsafe {
+		SUPPRESSION = Some(Suppression {
+			world_error,
+			null_log,
+			saved_log: None,
+		});
+	}
+}
+
+pub(crate) fn shutdown() {
+	end_suppression();
+
+	unsafe {
+		SUPPRESSION = None;
+	}
+}
+
+fn begin_suppression() -> bool {
+	// In case BYOND didn't unwind through the C++ hook last time
+	end_suppression();
+
+	let suppression = match unsafe { &mut SUPPRESSION } {
+		Some(suppression) => suppression,
+		None => return false,
+	};
+
+	if let Some(world_error) = suppression.world_error {
+		unsafe {
+			SKIPPED_WORLD_ERROR = Some(world_error);
+		}
+		return true;
+	}
+
+	let null_log = match &suppression.null_log {
+		Some(null_log) => null_log,
+		None => return false,
+	};
+
+	let world = Value::world();
+	let saved = match world.get(crate::byond_string!("log")) {
+		Ok(saved) => saved,
+		Err(_) => return false,
+	};
+
+	if world.set(crate::byond_string!("log"), null_log).is_err() {
+		return false;
+	}
+
+	suppression.saved_log = Some(saved);
+	true
+}
+
+// Called by the C++ runtime hook once BYOND is done with a suppressed runtime
+#[no_mangle]
+extern "C" fn end_suppressed_runtime() {
+	end_suppression();
+}
+
+fn end_suppression() {
+	unsafe {
+		SKIPPED_WORLD_ERROR = None;
+	}
+
+	let saved = match unsafe { &mut SUPPRESSION } {
+		Some(suppression) => suppression.saved_log.take(),
+		None => None,
+	};
+
+	if let Some(saved) = saved {
+		let _ = Value::world().set(crate::byond_string!("log"), saved);
+	}
+}
+
+// Returns true if this is the world/Error call for a suppressed runtime
+pub(crate) fn should_skip_call(proc_id: raw_types::procs::ProcId) -> bool {
+	unsafe { SKIPPED_WORLD_ERROR == Some(proc_id) }
+}
+
+impl<'a> RuntimeEvent<'a> {
+	pub(crate) fn new(message: &'a str) -> Self {
+		let ctx = unsafe {
+			if raw_types::funcs::CURRENT_EXECUTION_CONTEXT.is_null() {
+				std::ptr::null_mut()
+			} else {
+				*raw_types::funcs::CURRENT_EXECUTION_CONTEXT
+			}
+		};
+
+		Self {
+			message,
+			ctx,
+			stacks: RefCell::new(None),
+			suppressed: Cell::new(false),
+		}
+	}
+
+	/// The runtime's text, as BYOND would log it.
+	pub fn message(&self) -> &str {
+		self.message
+	}
+
+	/// The context that runtimed. Can be null if the runtime didn't come from a proc.
+	pub fn context(&self) -> *mut raw_types::procs::ExecutionContext {
+		self.ctx
+	}
+
+	fn instance(&self) -> Option<*mut raw_types::procs::ProcInstance> {
+		if self.ctx.is_null() {
+			return None;
+		}
+
+		let instance = unsafe { (*self.ctx).proc_instance };
+		if instance.is_null() {
+			None
+		} else {
+			Some(instance)
+		}
+	}
+
+	/// The proc that runtimed.
+	pub fn proc(&self) -> Option<Proc> {
+		self.instance()
+			.and_then(|instance| Proc::from_id(unsafe { (*instance).proc }))
+	}
+
+	pub fn file(&self) -> Option<String> {
+		if self.ctx.is_null() {
+			return None;
+		}
+
+		unsafe {
+			if (*self.ctx).filename.valid() {
+				Some(StringRef::from_id((*self.ctx).filename).to_string())
+			} else {
+				None
+			}
+		}
+	}
+
+	pub fn line(&self) -> Option<u32> {
+		self.file().map(|_| unsafe { (*self.ctx).line })
+	}
+
+	pub fn src(&self) -> Value {
+		match self.instance() {
+			Some(instance) => unsafe { Value::from_raw((*instance).src) },
+			None => Value::null(),
+		}
+	}
+
+	pub fn usr(&self) -> Value {
+		match self.instance() {
+			Some(instance) => unsafe { Value::from_raw((*instance).usr) },
+			None => Value::null(),
+		}
+	}
+
+	/// Every call stack at the time of the runtime. Built the first time it's asked for and shared after that.
+	pub fn call_stacks(&self) -> Ref<debug::CallStacks> {
+		if self.stacks.borrow().is_none() {
+			self.stacks.replace(Some(debug::CallStacks::new()));
+		}
+
+		Ref::map(self.stacks.borrow(), |x| x.as_ref().unwrap())
+	}
+
+	/// The runtime as a [Runtime](struct.Runtime.html), with its location filled in.
+	pub fn to_runtime(&self) -> Runtime {
+		unsafe {
+			Runtime::new(self.message)
+				.with_dm_message(self.message)
+				.with_context(self.ctx)
+		}
+	}
+
+	/// Keeps the runtime out of the logs. BYOND still stops the proc that runtimed.
+	///
+	/// Games that override `/world/Error` don't get the call for this runtime. Otherwise BYOND logs the runtime
+	/// itself, so `world.log` is pointed at the null device until it's done. That needs the debugger's
+	/// `/proc/auxtools_expr_stub` to make the file with, and without it the runtime is logged anyway.
+	pub fn suppress_logging(&self) {
+		self.suppressed.set(true);
+	}
+
+	pub fn is_logging_suppressed(&self) -> bool {
+		self.suppressed.get()
+	}
+}
+
+// Returns true if BYOND shouldn't log the runtime
+pub(crate) fn dispatch(message: &str) -> bool {
+	let event = RuntimeEvent::new(message);
+
+	// First, so handlers can tell whether the runtime is going to be logged
+	crate::runtime_aggregator::on_runtime(&event);
+
+	for func in inventory::iter::<RuntimeHook> {
+		func.0(&event);
+	}
+
+	event.is_logging_suppressed() && begin_suppression()
+}
@@ -0,0 +1,124 @@
+//! Signatures loaded from a file at init, for when BYOND updates faster than auxtools does.
+//!
+//! Pass `signatures=<path>` to `auxtools_init` to load a JSON file like this one:
+//! ```json
+//! [
+//!     {
+//!         "name": "to_string",
+//!         "os": "windows",
+//!         "min_build": 1590,
+//!         "pattern": "55 8B EC 6A FF 68 ?? ?? ?? ?? 64 A1 ?? ?? ?? ?? 50 83 EC 18"
+//!     }
+//! ]
+//! ```
+//! Patterns use the same syntax as [signature](../macro.signature.html). `os` (`windows` or `linux`), `min_build`
+//! and `max_build` (both inclusive) are optional and limit which BYOND builds an entry applies to. When more than
+//! one entry applies, the last one in the file wins.
+//!
+//! An entry replaces the built-in signature with the same name, as listed in the
+//! [init report](../init_report/index.html). Anything else is only there for plugins to look up with
+//! [get](fn.get.html). Every `auxtools_init` replaces the signatures loaded by the last one. Put the path in double
+//! quotes (`signatures="C:\My Server\signatures.json"`) if it contains spaces or commas.
+//!
+//! Only the pattern is replaced: auxtools still reads the match the way it reads the built-in one, so the match has
+//! to start where the built-in one does. `offset` (optional, 0 by default) is added to the match before that, which
+//! lets a pattern start with some bytes before the place auxtools expects. Where each built-in match must start:
+//! - `get_proc_array_entry`, `call_proc_by_id`, `dec_ref_count`, `dec_ref_count_513`, `dec_ref_count_514`,
+//!   `inc_ref_count`, `get_misc_by_id` and `runtime`: an `E8` call to the function.
+//! - `current_execution_context`, `suspended_procs`: one byte before the address of the global (`A1`/`A3 <addr>`).
+//! - `suspended_procs_buffer`: two bytes before the address of the global (`8B 35 <addr>`/`89 35 <addr>`).
+//! - `variable_names`: two bytes before the address of the global on Windows and on Linux before 514.1543
+//!   (`8B 1D <addr>`/`8B 35 <addr>`), one byte before it on Linux from 514.1543 on (`A1 <addr>`).
+//! - `string_table_count`: a function that has the address of the count 8 bytes in on Windows and 11 bytes in on Linux.
+//! - Everything else: the start of the function.
+
+use crate::*;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SignatureEntry {
+	pub name: String,
+	pub os: Option<String>,
+	pub min_build: Option<u32>,
+	pub max_build: Option<u32>,
+	pub pattern: String,
+	#[serde(default)]
+	pub offset: usize,
+}
+
+impl SignatureEntry {
+	/// Whether the entry is meant for this OS and BYOND build.
+	pub fn applies(&self) -> bool {
+		let os_matches = match self.os.as_deref() {
+			Some("windows") => cfg!(windows),
+			Some("linux") => cfg!(unix),
+			Some(_) => false,
+			None => true,
+		};
+
+		let build = version::get().1;
+
+		os_matches
+			&& self.min_build.map_or(true, |min| build >= min)
+			&& self.max_build.map_or(true, |max| build <= max)
+	}
+}
+
+thread_local! {
+	static ENTRIES: RefCell<Vec<(SignatureEntry, Vec<Option<u8>>)>> = RefCell::new(vec![]);
+}
+
+/// Replaces the loaded signatures with the ones in `path`. Nothing changes if the file can't be read or any entry
+/// is invalid.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<(), String> {
+	let path = path.as_ref();
+
+	let text =
+		fs::read_to_string(path).map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+
+	let entries: Vec<SignatureEntry> = serde_json::from_str(&text)
+		.map_err(|e| format!("couldn't parse {}: {}", path.display(), e))?;
+
+	let mut parsed = vec![];
+	for entry in entries {
+		if let Some(os) = entry.os.as_deref() {
+			if os != "windows" && os != "linux" {
+				return Err(format!("{}: unknown os {:?}", entry.name, os));
+			}
+		}
+
+		let signature = sigscan::parse_signature(&entry.pattern)
+			.map_err(|e| format!("{}: {}", entry.name, e))?;
+		parsed.push((entry, signature));
+	}
+
+	ENTRIES.with(|x| x.replace(parsed));
+	Ok(())
+}
+
+/// Forgets every loaded signature.
+pub fn clear() {
+	ENTRIES.with(|x| x.borrow_mut().clear());
+}
+
+/// The loaded signature for `name` that applies to this OS and BYOND build, if there is one.
+///
+/// The BYOND build isn't known until `auxtools_init` has started, so this should only be used after that.
+pub fn get(name: &str) -> Option<Vec<Option<u8>>> {
+	get_with_offset(name).map(|(signature, _)| signature)
+}
+
+/// Like [get](fn.get.html), but also returns the entry's `offset`, which should be added to wherever the signature
+/// matches.
+pub fn get_with_offset(name: &str) -> Option<(Vec<Option<u8>>, usize)> {
+	ENTRIES.with(|x| {
+		x.borrow()
+			.iter()
+			.rev()
+			.find(|(entry, _)| entry.name == name && entry.applies())
+			.map(|(entry, signature)| (signature.clone(), entry.offset))
+	})
+}
@@ -29,3 +29,23 @@ macro_rules! signatures {
 		};
 	}
 }
+
+/// Converts a signature like `"55 8B EC ?? ??"` at runtime, using the same syntax as [signature](../macro.signature.html).
+pub fn parse_signature(signature: &str) -> Result<Vec<Option<u8>>, String> {
+	let signature: Vec<Option<u8>> = signature
+		.split_whitespace()
+		.map(|byte| match byte {
+			"??" => Ok(None),
+			_ if byte.len() == 2 => u8::from_str_radix(byte, 16)
+				.map(Some)
+				.map_err(|_| format!("invalid byte {:?}", byte)),
+			_ => Err(format!("invalid byte {:?}", byte)),
+		})
+		.collect::<Result<_, _>>()?;
+
+	if signature.is_empty() {
+		return Err("signature is empty".to_owned());
+	}
+
+	Ok(signature)
+}
@@ -57,6 +57,11 @@ impl StringRef {
 		unsafe { self.value.raw.data.string }
 	}
 
+	/// Builds a string out of text runs and macros. See [TextBuilder](struct.TextBuilder.html) for a friendlier interface.
+	pub fn from_tokens(tokens: &[TextToken]) -> DMResult<Self> {
+		Self::from_raw(&TextToken::encode(tokens))
+	}
+
 	pub fn data(&self) -> &[u8] {
 		unsafe {
 			let id = self.value.raw.data.string;
@@ -65,6 +70,16 @@ impl StringRef {
 			CStr::from_ptr((*entry).data).to_bytes()
 		}
 	}
+
+	/// Decodes the text macros embedded in this string.
+	pub fn tokens(&self) -> Vec<TextToken> {
+		TextToken::decode(self.data())
+	}
+
+	/// Returns the string's contents with every text macro removed.
+	pub fn strip_macros(&self) -> String {
+		String::from_utf8_lossy(&TextToken::strip(self.data())).into_owned()
+	}
 }
 
 impl Clone for StringRef {
@@ -83,81 +98,33 @@ impl fmt::Debug for StringRef {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 		let mut format = vec![];
 
-		let mut iter = self.data().into_iter();
-
-		loop {
-			let byte = match iter.next() {
-				Some(x) => *x,
-				None => break,
+		for token in self.tokens() {
+			let text = match token {
+				TextToken::Text(text) => text,
+				TextToken::Macro { kind, .. } => {
+					format.extend_from_slice(kind.dm_syntax().as_bytes());
+					continue;
+				}
 			};
 
-			if byte == 0xFF {
-				// NOTE: Doesn't hold state for formatting, so some strings relying on are a little off
-				format.extend_from_slice(match iter.next() {
-					None => break,
-					Some(1) | Some(2) | Some(3) | Some(4) => b"[]",
-					Some(5) => b"[]\\th",
-					Some(6) => b"\\a",
-					Some(7) => b"\\A",
-					Some(8) => b"\\the",
-					Some(9) => b"\\The",
-					Some(10) => b"\\he",
-					Some(11) => b"\\He",
-					Some(12) => b"\\his",
-					Some(13) => b"\\His",
-					Some(14) => b"\\hers",
-					Some(15) => b"\\Hers",
-					Some(16) => b"\\him ",
-					Some(17) => b"\\himself",
-					Some(18) => b"\\... ",
-					Some(19) => b"\\n",
-					Some(20) => b"\\s ",
-					Some(21) => b"\\proper ",
-					Some(22) => b"\\improper ",
-					Some(23) => b"\\bold ",
-					Some(24) => b"\\italic ",
-					Some(25) => b"\\underline ",
-					Some(26) => b"\\strike ",
-					Some(27) => b"\\font",
-					Some(28) => b"\\color",
-					Some(29) => b"\\font",
-					Some(30) => b"\\color",
-					Some(31) => b"\\red ",
-					Some(32) => b"\\green ",
-					Some(33) => b"\\blue ",
-					Some(34) => b"\\black ",
-					Some(35) => b"\\white ",
-					Some(36) => b"\\yellow ",
-					Some(37) => b"\\cyan ",
-					Some(38) => b"\\magenta ",
-					Some(39) => b"\\beep ",
-					Some(40) => b"\\link",
-					Some(41) => b" \\link",
-					Some(42) => b"\\ref[]",
-					Some(43) => b"\\icon[]",
-					Some(44) => b"\\roman[]",
-					Some(45) => b"\\Roman[]",
-					Some(_) => b"[UNKNONWN FORMAT SPECIFIER]",
-				});
-				continue;
-			}
+			for byte in text {
+				if byte == b'\n' {
+					format.extend_from_slice(b"\\n");
+					continue;
+				}
 
-			if byte == b'\n' {
-				format.extend_from_slice(b"\\n");
-				continue;
-			}
+				if byte == b'\r' {
+					format.extend_from_slice(b"\\r");
+					continue;
+				}
 
-			if byte == b'\r' {
-				format.extend_from_slice(b"\\r");
-				continue;
-			}
+				// Escape \[]"" chars
+				if byte == b'\\' || byte == b'[' || byte == b']' || byte == b'"' {
+					format.push(b'\\');
+				}
 
-			// Escape \[]"" chars
-			if byte == b'\\' || byte == b'[' || byte == b']' || byte == b'"' {
-				format.push(b'\\');
+				format.push(byte);
 			}
-
-			format.push(byte);
 		}
 
 		write!(f, "\"{}\"", String::from_utf8_lossy(&format))
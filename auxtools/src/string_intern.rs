@@ -1,6 +1,8 @@
-use std::cell::UnsafeCell;
+use std::cell::{RefCell, UnsafeCell};
+use std::collections::HashMap;
 
 use crate::inventory;
+use crate::DMResult;
 use crate::StringRef;
 
 #[macro_export]
@@ -16,11 +18,68 @@ macro_rules! byond_string {
 	};
 }
 
-#[doc(hidden)]
-pub struct InternedString(pub &'static str, pub UnsafeCell<Option<StringRef>>);
+/// Strings that are kept alive in BYOND's string table so they don't have to be looked up over and over.
+///
+/// Literals should use [byond_string](macro.byond_string.html). Strings only known at runtime can use [intern](#method.intern).
+pub struct InternedString(
+	#[doc(hidden)] pub &'static str,
+	#[doc(hidden)] pub UnsafeCell<Option<StringRef>>,
+);
 
 inventory::collect!(&'static InternedString);
 
+struct RuntimeStrings {
+	strings: HashMap<Box<str>, StringRef>,
+	capacity: Option<usize>,
+}
+
+thread_local! {
+	static RUNTIME_STRINGS: RefCell<RuntimeStrings> = RefCell::new(RuntimeStrings {
+		strings: HashMap::new(),
+		capacity: None,
+	});
+}
+
+impl InternedString {
+	/// Gets a string created at runtime, only asking BYOND for it the first time it is seen.
+	///
+	/// Cached strings live until the library shuts down. Once the cache is full (see [set_capacity](#method.set_capacity))
+	/// new strings are still returned but not remembered.
+	pub fn intern(string: &str) -> DMResult<StringRef> {
+		if let Some(cached) = RUNTIME_STRINGS.with(|x| x.borrow().strings.get(string).cloned()) {
+			return Ok(cached);
+		}
+
+		let created = StringRef::new(string)?;
+
+		RUNTIME_STRINGS.with(|x| {
+			let mut state = x.borrow_mut();
+			let is_full = match state.capacity {
+				Some(capacity) => state.strings.len() >= capacity,
+				None => false,
+			};
+
+			if !is_full {
+				state.strings.insert(string.into(), created.clone());
+			}
+		});
+
+		Ok(created)
+	}
+
+	/// Limits how many strings [intern](#method.intern) will cache. `None` means no limit.
+	///
+	/// Lowering the limit doesn't evict anything that is already cached.
+	pub fn set_capacity(capacity: Option<usize>) {
+		RUNTIME_STRINGS.with(|x| x.borrow_mut().capacity = capacity);
+	}
+
+	/// The number of strings currently cached by [intern](#method.intern).
+	pub fn interned_count() -> usize {
+		RUNTIME_STRINGS.with(|x| x.borrow().strings.len())
+	}
+}
+
 pub fn setup_interned_strings() {
 	for info in inventory::iter::<&'static InternedString> {
 		let string = StringRef::new(info.0).expect("failed to create interned string");
@@ -33,6 +92,8 @@ pub fn setup_interned_strings() {
 }
 
 pub fn destroy_interned_strings() {
+	RUNTIME_STRINGS.with(|x| x.borrow_mut().strings.clear());
+
 	for info in inventory::iter::<&'static InternedString> {
 		unsafe {
 			let dst = &mut *info.1.get();
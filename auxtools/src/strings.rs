@@ -0,0 +1,179 @@
+//! Tools for looking at the contents of BYOND's string table.
+//!
+//! Strings that are never released show up here as entries with reference counts that only ever grow.
+//! Comparing [stats](fn.stats.html) over time and searching by [prefix](fn.find_by_prefix.html) is usually enough to
+//! work out which code is holding on to them.
+
+use crate::raw_types::{funcs, strings};
+use std::ffi::CStr;
+
+/// Upper bounds (inclusive) of the size buckets reported by [stats](fn.stats.html). Anything longer goes into a final bucket.
+pub const SIZE_BUCKETS: &[usize] = &[15, 63, 255, 1023, 4095];
+
+/// A copy of a single string table entry.
+#[derive(Clone, Debug)]
+pub struct StringInfo {
+	pub id: strings::StringId,
+	pub ref_count: u32,
+	pub data: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SizeBucket {
+	/// The longest string that fits in this bucket, or None for the last bucket.
+	pub max_len: Option<usize>,
+	pub count: usize,
+	pub bytes: usize,
+	pub references: u64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct StringTableStats {
+	/// Number of strings in the table.
+	pub count: usize,
+	/// Strings that exist but have no references.
+	pub unreferenced: usize,
+	/// Total length of every string, not counting terminators.
+	pub bytes: usize,
+	/// Sum of every string's reference count.
+	pub references: u64,
+	pub buckets: Vec<SizeBucket>,
+}
+
+/// Whether the size of the string table was found during init. Everything in this module reports no strings if it wasn't.
+pub fn available() -> bool {
+	unsafe { !funcs::STRING_TABLE_COUNT.is_null() }
+}
+
+// Called during init. Forgets the table's size if it doesn't look right, which disables this module
+// rather than letting it walk garbage.
+pub(crate) fn validate_table_count() {
+	unsafe {
+		if funcs::STRING_TABLE_COUNT.is_null() {
+			return;
+		}
+
+		// There's always at least one string, and get_string_table_entry does its own bounds-checking
+		let len = *funcs::STRING_TABLE_COUNT;
+		let mut entry: *mut strings::StringEntry = std::ptr::null_mut();
+		if len == 0 || funcs::get_string_table_entry(&mut entry, strings::StringId(len - 1)) != 1 {
+			funcs::STRING_TABLE_COUNT = std::ptr::null();
+		}
+	}
+}
+
+fn table_len() -> u32 {
+	unsafe {
+		if funcs::STRING_TABLE_COUNT.is_null() {
+			return 0;
+		}
+		*funcs::STRING_TABLE_COUNT
+	}
+}
+
+// Calls `f` for every live entry in the table. The entry is only valid for the duration of the call.
+fn visit<F: FnMut(strings::StringId, &strings::StringEntry)>(mut f: F) {
+	for i in 0..table_len() {
+		let id = strings::StringId(i);
+		let mut entry: *mut strings::StringEntry = std::ptr::null_mut();
+
+		unsafe {
+			if funcs::get_string_table_entry(&mut entry, id) != 1 {
+				continue;
+			}
+
+			// Freed slots are left empty until BYOND reuses them
+			if entry.is_null() || (*entry).data.is_null() {
+				continue;
+			}
+
+			f(id, &*entry);
+		}
+	}
+}
+
+fn entry_data(entry: &strings::StringEntry) -> &[u8] {
+	unsafe { CStr::from_ptr(entry.data).to_bytes() }
+}
+
+/// Copies every string out of the string table.
+pub fn entries() -> Vec<StringInfo> {
+	let mut res = vec![];
+
+	visit(|id, entry| {
+		res.push(StringInfo {
+			id,
+			ref_count: entry.ref_count,
+			data: entry_data(entry).to_vec(),
+		});
+	});
+
+	res
+}
+
+/// Finds every string starting with `prefix`.
+pub fn find_by_prefix<S: AsRef<[u8]>>(prefix: S) -> Vec<StringInfo> {
+	let prefix = prefix.as_ref();
+	let mut res = vec![];
+
+	visit(|id, entry| {
+		let data = entry_data(entry);
+		if data.starts_with(prefix) {
+			res.push(StringInfo {
+				id,
+				ref_count: entry.ref_count,
+				data: data.to_vec(),
+			});
+		}
+	});
+
+	res
+}
+
+/// Returns the `n` strings with the most references, highest first.
+pub fn most_referenced(n: usize) -> Vec<StringInfo> {
+	let mut all = entries();
+	all.sort_by(|a, b| b.ref_count.cmp(&a.ref_count));
+	all.truncate(n);
+	all
+}
+
+/// Summarizes the string table without copying any strings.
+pub fn stats() -> StringTableStats {
+	let mut buckets: Vec<SizeBucket> = SIZE_BUCKETS
+		.iter()
+		.map(|x| Some(*x))
+		.chain(std::iter::once(None))
+		.map(|max_len| SizeBucket {
+			max_len,
+			..Default::default()
+		})
+		.collect();
+
+	let mut stats = StringTableStats::default();
+
+	visit(|_, entry| {
+		let len = entry_data(entry).len();
+		let refs = entry.ref_count as u64;
+
+		stats.count += 1;
+		stats.bytes += len;
+		stats.references += refs;
+		if entry.ref_count == 0 {
+			stats.unreferenced += 1;
+		}
+
+		let bucket = SIZE_BUCKETS
+			.iter()
+			.position(|max| len <= *max)
+			.unwrap_or(SIZE_BUCKETS.len());
+
+		let bucket = &mut buckets[bucket];
+		bucket.count += 1;
+		bucket.bytes += len;
+		bucket.references += refs;
+	});
+
+	stats.buckets = buckets;
+	stats
+}
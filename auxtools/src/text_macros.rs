@@ -0,0 +1,387 @@
+//
+// BYOND stores text macros (\the, \proper, \icon, etc.) inside strings as a 0xFF byte followed by a code byte.
+// Compiled format strings also use this encoding for their `[]` placeholders, so one string can contain
+// a mix of literal text, macros and references to the expressions that get embedded into it at runtime.
+//
+// Some macros don't stand alone. `\the` and `\a` apply to the expression that follows them, while
+// `\he`, `\his` and `\s` look at the previous one (or the next one when nothing came before).
+// `TextToken::decode` tracks that state so each macro knows which embedded expression it belongs to.
+//
+
+use crate::*;
+
+const MACRO_PREFIX: u8 = 0xFF;
+
+macro_rules! text_macros {
+	( $( $code:literal => $name:ident, $syntax:literal; )* ) => {
+		/// A single text macro from BYOND's internal string encoding.
+		#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+		pub enum TextMacro {
+			/// An embedded expression (`[]`). BYOND has a few variants of these, the code is kept as-is.
+			Expression(u8),
+			$( $name, )*
+			/// A code we don't know about. Kept so that re-encoding doesn't lose anything.
+			Unknown(u8),
+		}
+
+		impl TextMacro {
+			pub fn from_code(code: u8) -> Self {
+				match code {
+					1..=4 => TextMacro::Expression(code),
+					$( $code => TextMacro::$name, )*
+					_ => TextMacro::Unknown(code),
+				}
+			}
+
+			pub fn code(&self) -> u8 {
+				match self {
+					TextMacro::Expression(code) => *code,
+					$( TextMacro::$name => $code, )*
+					TextMacro::Unknown(code) => *code,
+				}
+			}
+
+			/// How this macro is spelled in DM source code.
+			pub fn dm_syntax(&self) -> &'static str {
+				match self {
+					TextMacro::Expression(_) => "[]",
+					$( TextMacro::$name => $syntax, )*
+					TextMacro::Unknown(_) => "[UNKNOWN FORMAT SPECIFIER]",
+				}
+			}
+		}
+	};
+}
+
+text_macros! {
+	5 => Ordinal, "[]\\th";
+	6 => A, "\\a";
+	7 => CapitalA, "\\A";
+	8 => The, "\\the";
+	9 => CapitalThe, "\\The";
+	10 => He, "\\he";
+	11 => CapitalHe, "\\He";
+	12 => His, "\\his";
+	13 => CapitalHis, "\\His";
+	14 => Hers, "\\hers";
+	15 => CapitalHers, "\\Hers";
+	16 => Him, "\\him ";
+	17 => Himself, "\\himself";
+	18 => NoNewline, "\\... ";
+	19 => Newline, "\\n";
+	20 => Plural, "\\s ";
+	21 => Proper, "\\proper ";
+	22 => Improper, "\\improper ";
+	23 => Bold, "\\bold ";
+	24 => Italic, "\\italic ";
+	25 => Underline, "\\underline ";
+	26 => Strike, "\\strike ";
+	27 => Font, "\\font";
+	28 => Color, "\\color";
+	29 => FontEnd, "\\font";
+	30 => ColorEnd, "\\color";
+	31 => Red, "\\red ";
+	32 => Green, "\\green ";
+	33 => Blue, "\\blue ";
+	34 => Black, "\\black ";
+	35 => White, "\\white ";
+	36 => Yellow, "\\yellow ";
+	37 => Cyan, "\\cyan ";
+	38 => Magenta, "\\magenta ";
+	39 => Beep, "\\beep ";
+	40 => Link, "\\link";
+	41 => LinkEnd, " \\link";
+	42 => Ref, "\\ref[]";
+	43 => Icon, "\\icon[]";
+	44 => Roman, "\\roman[]";
+	45 => CapitalRoman, "\\Roman[]";
+}
+
+/// Which embedded expression (if any) a macro uses.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ArgumentKind {
+	None,
+	/// The macro is the embedded expression.
+	Own,
+	/// The macro applies to the next embedded expression.
+	Following,
+	/// The macro applies to the last embedded expression, or the next one if there hasn't been any yet.
+	Preceding,
+}
+
+impl TextMacro {
+	fn argument_kind(&self) -> ArgumentKind {
+		match self {
+			TextMacro::Expression(_)
+			| TextMacro::Ordinal
+			| TextMacro::Ref
+			| TextMacro::Icon
+			| TextMacro::Roman
+			| TextMacro::CapitalRoman => ArgumentKind::Own,
+
+			TextMacro::A | TextMacro::CapitalA | TextMacro::The | TextMacro::CapitalThe => {
+				ArgumentKind::Following
+			}
+
+			TextMacro::He
+			| TextMacro::CapitalHe
+			| TextMacro::His
+			| TextMacro::CapitalHis
+			| TextMacro::Hers
+			| TextMacro::CapitalHers
+			| TextMacro::Him
+			| TextMacro::Himself
+			| TextMacro::Plural => ArgumentKind::Preceding,
+
+			_ => ArgumentKind::None,
+		}
+	}
+
+	/// Returns true if this macro is replaced by an embedded expression when the string is formatted.
+	pub fn is_expression(&self) -> bool {
+		self.argument_kind() == ArgumentKind::Own
+	}
+}
+
+/// A piece of a decoded string.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TextToken {
+	/// Literal text.
+	Text(Vec<u8>),
+
+	/// A text macro. `argument` is the index of the embedded expression it refers to, if any.
+	Macro {
+		kind: TextMacro,
+		argument: Option<usize>,
+	},
+}
+
+impl TextToken {
+	/// Splits BYOND's internal string encoding into text runs and macros.
+	pub fn decode(data: &[u8]) -> Vec<TextToken> {
+		let mut tokens = vec![];
+		let mut text = vec![];
+		let mut iter = data.iter();
+
+		while let Some(&byte) = iter.next() {
+			if byte != MACRO_PREFIX {
+				text.push(byte);
+				continue;
+			}
+
+			// A trailing 0xFF doesn't mean anything, so it's dropped like BYOND would
+			let code = match iter.next() {
+				Some(&code) => code,
+				None => break,
+			};
+
+			if !text.is_empty() {
+				tokens.push(TextToken::Text(std::mem::take(&mut text)));
+			}
+
+			tokens.push(TextToken::Macro {
+				kind: TextMacro::from_code(code),
+				argument: None,
+			});
+		}
+
+		if !text.is_empty() {
+			tokens.push(TextToken::Text(text));
+		}
+
+		Self::bind_arguments(&mut tokens);
+		tokens
+	}
+
+	/// Builds BYOND's internal string encoding out of a list of tokens.
+	/// Macro arguments are implied by token order, so they aren't written out.
+	pub fn encode(tokens: &[TextToken]) -> Vec<u8> {
+		let mut data = vec![];
+
+		for token in tokens {
+			match token {
+				TextToken::Text(text) => data.extend_from_slice(text),
+				TextToken::Macro { kind, .. } => {
+					data.push(MACRO_PREFIX);
+					data.push(kind.code());
+				}
+			}
+		}
+
+		data
+	}
+
+	/// Removes every macro from a string, leaving only the literal text.
+	pub fn strip(data: &[u8]) -> Vec<u8> {
+		Self::decode(data)
+			.into_iter()
+			.filter_map(|token| match token {
+				TextToken::Text(text) => Some(text),
+				TextToken::Macro { .. } => None,
+			})
+			.flatten()
+			.collect()
+	}
+
+	fn bind_arguments(tokens: &mut [TextToken]) {
+		let expression_count = tokens
+			.iter()
+			.filter(|token| matches!(token, TextToken::Macro { kind, .. } if kind.is_expression()))
+			.count();
+
+		let mut next_expression = 0;
+
+		for token in tokens.iter_mut() {
+			if let TextToken::Macro { kind, argument } = token {
+				let index = match kind.argument_kind() {
+					ArgumentKind::None => None,
+					ArgumentKind::Own => {
+						next_expression += 1;
+						Some(next_expression - 1)
+					}
+					ArgumentKind::Following => Some(next_expression),
+					ArgumentKind::Preceding => Some(next_expression.max(1) - 1),
+				};
+
+				*argument = index.filter(|x| *x < expression_count);
+			}
+		}
+	}
+}
+
+/// Helps build strings that contain text macros.
+///
+/// # Examples
+///
+/// This is equivalent to `"\improper crowbar"` in DM.
+/// ```ignore
+/// let name = TextBuilder::new()
+///     .push(TextMacro::Improper)
+///     .text("crowbar")
+///     .to_string_ref()?;
+/// ```
+#[derive(Clone, Default)]
+pub struct TextBuilder {
+	tokens: Vec<TextToken>,
+}
+
+impl TextBuilder {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Appends literal text.
+	pub fn text<S: AsRef<str>>(mut self, text: S) -> Self {
+		self.tokens
+			.push(TextToken::Text(text.as_ref().as_bytes().to_vec()));
+		self
+	}
+
+	/// Appends a macro.
+	pub fn push(mut self, kind: TextMacro) -> Self {
+		self.tokens.push(TextToken::Macro {
+			kind,
+			argument: None,
+		});
+		self
+	}
+
+	pub fn tokens(&self) -> &[TextToken] {
+		&self.tokens
+	}
+
+	pub fn to_bytes(&self) -> Vec<u8> {
+		TextToken::encode(&self.tokens)
+	}
+
+	pub fn to_string_ref(&self) -> DMResult<StringRef> {
+		StringRef::from_raw(&self.to_bytes())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn arguments(data: &[u8]) -> Vec<Option<usize>> {
+		TextToken::decode(data)
+			.into_iter()
+			.filter_map(|token| match token {
+				TextToken::Macro { argument, .. } => Some(argument),
+				TextToken::Text(_) => None,
+			})
+			.collect()
+	}
+
+	#[test]
+	fn following_macros_bind_to_the_next_expression() {
+		// "\the [] and \a []"
+		let data = b"\xFF\x08\xFF\x01 and \xFF\x06\xFF\x01";
+		assert_eq!(arguments(data), vec![Some(0), Some(0), Some(1), Some(1)]);
+
+		// "[] \the" has nothing after it to apply to
+		assert_eq!(arguments(b"\xFF\x01 \xFF\x08"), vec![Some(0), None]);
+	}
+
+	#[test]
+	fn preceding_macros_bind_to_the_last_expression() {
+		// "[] [] \his"
+		let data = b"\xFF\x01 \xFF\x01 \xFF\x0C";
+		assert_eq!(arguments(data), vec![Some(0), Some(1), Some(1)]);
+
+		// "\he []" has nothing before it, so it uses the next one
+		assert_eq!(arguments(b"\xFF\x0A \xFF\x01"), vec![Some(0), Some(0)]);
+
+		// "\he" on its own has nothing to apply to
+		assert_eq!(arguments(b"\xFF\x0A"), vec![None]);
+	}
+
+	#[test]
+	fn unrelated_macros_have_no_argument() {
+		assert_eq!(arguments(b"\xFF\x16crowbar \xFF\x01"), vec![None, Some(0)]);
+	}
+
+	#[test]
+	fn encoding_round_trips() {
+		let data = b"\xFF\x08\xFF\x01 hits \xFF\x0C\xFF\xC8 head";
+		let tokens = TextToken::decode(data);
+
+		assert_eq!(
+			tokens[4],
+			TextToken::Macro {
+				kind: TextMacro::Unknown(0xC8),
+				argument: None,
+			}
+		);
+		assert_eq!(TextToken::encode(&tokens), data.to_vec());
+		assert_eq!(TextToken::strip(data), b" hits  head".to_vec());
+	}
+
+	#[test]
+	fn trailing_prefix_is_dropped() {
+		assert_eq!(
+			TextToken::encode(&TextToken::decode(b"crowbar\xFF")),
+			b"crowbar".to_vec()
+		);
+	}
+
+	#[test]
+	fn builder_matches_decode() {
+		let built = TextBuilder::new()
+			.push(TextMacro::Improper)
+			.text("crowbar")
+			.to_bytes();
+
+		assert_eq!(built, b"\xFF\x16crowbar".to_vec());
+		assert_eq!(
+			TextToken::decode(&built),
+			vec![
+				TextToken::Macro {
+					kind: TextMacro::Improper,
+					argument: None,
+				},
+				TextToken::Text(b"crowbar".to_vec()),
+			]
+		);
+	}
+}
@@ -1,8 +1,10 @@
 use super::raw_types;
 use super::string;
 use crate::list;
+use crate::require_function;
 use crate::runtime;
 use crate::runtime::DMResult;
+use crate::variable_intern::InternedVariable;
 use std::ffi::CString;
 use std::fmt;
 use std::marker::PhantomData;
@@ -197,6 +199,28 @@ impl Value {
 		Ok(())
 	}
 
+	/// Gets a variable by a name resolved with [byond_var](macro.byond_var.html).
+	///
+	/// This is the same name-based lookup [get](#method.get) does with a [byond_string](macro.byond_string.html),
+	/// it isn't any faster. [byond_var](macro.byond_var.html) is only useful when you also want the variable's
+	/// id, see [InternedVariable::id](struct.InternedVariable.html#method.id).
+	pub fn get_var(&self, var: &InternedVariable) -> DMResult {
+		match var.string_id() {
+			Some(id) => self.get_by_id(id),
+			None => self.get(string::StringRef::new(var.name())?),
+		}
+	}
+
+	/// Sets a variable by a name resolved with [byond_var](macro.byond_var.html).
+	pub fn set_var<V: Into<Value>>(&self, var: &InternedVariable, value: V) -> DMResult<()> {
+		let value = value.into();
+
+		match var.string_id() {
+			Some(id) => self.set_by_id(id, value.raw),
+			None => self.set_by_id(string::StringRef::new(var.name())?.get_id(), value.raw),
+		}
+	}
+
 	/// Check if the current value is a number and casts it.
 	pub fn as_number(&self) -> DMResult<f32> {
 		match self.raw.tag {
@@ -260,7 +284,21 @@ impl Value {
 			}
 		}
 
-		Err(runtime!("External proc call failed"))
+		let path = match self.get_type() {
+			Ok(type_path) => format!("{}/proc/{}", type_path, procname.as_ref()),
+			Err(_) => procname.as_ref().to_owned(),
+		};
+
+		Err(runtime::Runtime::new(format!("call to {} failed", path))
+			.with_proc(path)
+			.at_current_location())
+	}
+
+	/// Like [call](#method.call), but fails with the first DM runtime thrown during the call, including `CRASH()`.
+	///
+	/// The return value is thrown away if anything runtimed, even if DM code caught it.
+	pub fn call_checked<S: AsRef<str>>(&self, procname: S, args: &[&Value]) -> DMResult {
+		runtime::catch_runtimes(|| self.call(procname, args))
 	}
 
 	// ugh
@@ -275,6 +313,8 @@ impl Value {
 			_ => {}
 		}
 
+		require_function(unsafe { raw_types::funcs::to_string_byond }, "to_string")?;
+
 		let mut id = raw_types::strings::StringId(0);
 
 		unsafe {
@@ -294,6 +334,8 @@ impl Value {
 			_ => {}
 		}
 
+		require_function(unsafe { raw_types::funcs::to_string_byond }, "to_string")?;
+
 		let mut id = raw_types::strings::StringId(0);
 
 		unsafe {
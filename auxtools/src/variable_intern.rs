@@ -0,0 +1,111 @@
+use std::cell::UnsafeCell;
+use std::collections::HashMap;
+
+use crate::inventory;
+use crate::raw_types;
+use crate::StringRef;
+
+/// Resolves a variable name once during initialization, for use with [Value::get_var](struct.Value.html#method.get_var) and friends.
+///
+/// # Examples
+/// ```ignore
+/// let health = src.get_var(byond_var!("health"))?;
+/// ```
+#[macro_export]
+macro_rules! byond_var {
+	($s:literal) => {
+		unsafe {
+			static mut STORE: $crate::InternedVariable = $crate::InternedVariable::new($s);
+			$crate::inventory::submit!(unsafe { &STORE });
+			&STORE
+		}
+	};
+}
+
+/// A variable name that was looked up in BYOND's variable name table on initialization.
+pub struct InternedVariable {
+	name: &'static str,
+	resolved: UnsafeCell<Option<ResolvedVariable>>,
+}
+
+struct ResolvedVariable {
+	string: StringRef,
+	id: Option<raw_types::strings::VariableId>,
+}
+
+inventory::collect!(&'static InternedVariable);
+
+impl InternedVariable {
+	#[doc(hidden)]
+	pub const fn new(name: &'static str) -> Self {
+		Self {
+			name,
+			resolved: UnsafeCell::new(None),
+		}
+	}
+
+	pub fn name(&self) -> &'static str {
+		self.name
+	}
+
+	/// The variable's id, or None if no type in the world has a variable with this name.
+	pub fn id(&self) -> Option<raw_types::strings::VariableId> {
+		self.resolved().and_then(|x| x.id)
+	}
+
+	/// The cached string id to pass to BYOND, or None if we haven't been initialized yet.
+	///
+	/// Resolved names use the string stored in the variable name table; unresolved ones use
+	/// a string created once at init, so neither path allocates a string per access.
+	pub(crate) fn string_id(&self) -> Option<raw_types::strings::StringId> {
+		self.resolved().map(|x| x.string.get_id())
+	}
+
+	fn resolved(&self) -> Option<&ResolvedVariable> {
+		unsafe { (*self.resolved.get()).as_ref() }
+	}
+}
+
+pub fn setup_interned_variables() -> Result<(), String> {
+	// Only walk the table once, no matter how many variables we're resolving
+	let mut ids = HashMap::new();
+	unsafe {
+		let table = &*raw_types::funcs::VARIABLE_NAMES;
+		for i in 0..table.count {
+			ids.entry((*table.entries.add(i as usize)).0)
+				.or_insert(raw_types::strings::VariableId(i));
+		}
+	}
+
+	for info in inventory::iter::<&'static InternedVariable> {
+		let string = StringRef::new(info.name).map_err(|_| {
+			format!(
+				"Couldn't create a string for interned variable {}",
+				info.name
+			)
+		})?;
+		let id = ids.get(&string.get_id().0).copied();
+
+		// Hold on to the table's own entry when we have one
+		let string = match id {
+			Some(id) => unsafe { StringRef::from_variable_id(id) },
+			None => string,
+		};
+
+		unsafe {
+			let dst = &mut *info.resolved.get();
+			*dst = Some(ResolvedVariable { string, id });
+		}
+	}
+
+	Ok(())
+}
+
+pub fn destroy_interned_variables() {
+	for info in inventory::iter::<&'static InternedVariable> {
+		unsafe {
+			let dst = &mut *info.resolved.get();
+			*dst = None;
+		}
+	}
+}
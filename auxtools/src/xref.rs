@@ -0,0 +1,127 @@
+//! An index of which procs read and write each variable, built by disassembling every proc.
+//!
+//! Operators that change a var in place, like `+=` or `++`, count as both a read and a write.
+//! Names aren't tied to a type: `health` means every var called `health`.
+
+use crate::*;
+use dmasm::operands::Variable;
+use dmasm::{Instruction, Node};
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Clone, Debug, Default)]
+pub struct VarAccess {
+	/// Paths of the procs that read the variable.
+	pub readers: BTreeSet<String>,
+	/// Paths of the procs that write the variable.
+	pub writers: BTreeSet<String>,
+}
+
+// The var an operator like `+=` or `++` changes in place
+fn modified_var(ins: &Instruction) -> Option<&Variable> {
+	match ins {
+		Instruction::AugAdd(var)
+		| Instruction::AugSub(var)
+		| Instruction::AugMul(var)
+		| Instruction::AugDiv(var)
+		| Instruction::AugMod(var)
+		| Instruction::AugBand(var)
+		| Instruction::AugBor(var)
+		| Instruction::AugXor(var)
+		| Instruction::AugLShift(var)
+		| Instruction::AugRShift(var)
+		| Instruction::PreInc(var)
+		| Instruction::PreDec(var)
+		| Instruction::PostInc(var)
+		| Instruction::PostDec(var) => Some(var),
+		_ => None,
+	}
+}
+
+#[derive(Default)]
+pub struct VarXref {
+	vars: BTreeMap<String, VarAccess>,
+}
+
+impl VarXref {
+	/// Disassembles every proc in the world. This can take a while on big codebases.
+	pub fn build() -> Self {
+		let mut xref = Self::default();
+
+		for proc in Proc::all() {
+			let bytecode = instruction_hooks::unhooked_bytecode(&proc);
+
+			let mut env = DisassembleEnv;
+			let (nodes, _error) = dmasm::disassembler::disassemble(&bytecode, &mut env);
+
+			for node in nodes {
+				match node {
+					Node::Instruction(Instruction::GetVar(var), _) => {
+						xref.visit(&proc.path, &var, false)
+					}
+					Node::Instruction(Instruction::SetVar(var), _) => {
+						xref.visit(&proc.path, &var, true)
+					}
+					Node::Instruction(ins, _) => {
+						if let Some(var) = modified_var(&ins) {
+							xref.visit(&proc.path, var, false);
+							xref.visit(&proc.path, var, true);
+						}
+					}
+					_ => {}
+				}
+			}
+		}
+
+		xref
+	}
+
+	// `a.b.c = 1` reads a and b, and writes c
+	fn visit(&mut self, proc: &str, var: &Variable, write: bool) {
+		match var {
+			Variable::Field(name) => {
+				let access = self
+					.vars
+					.entry(String::from_utf8_lossy(&name.0).into_owned())
+					.or_default();
+
+				if write {
+					access.writers.insert(proc.to_owned());
+				} else {
+					access.readers.insert(proc.to_owned());
+				}
+			}
+
+			Variable::SetCache(lhs, rhs) => {
+				self.visit(proc, lhs, false);
+				self.visit(proc, rhs, write);
+			}
+
+			_ => {}
+		}
+	}
+
+	pub fn get(&self, name: &str) -> Option<&VarAccess> {
+		self.vars.get(name)
+	}
+
+	/// Procs that read `name`.
+	pub fn readers(&self, name: &str) -> Vec<&str> {
+		match self.vars.get(name) {
+			Some(access) => access.readers.iter().map(|x| x.as_str()).collect(),
+			None => vec![],
+		}
+	}
+
+	/// Procs that write `name`.
+	pub fn writers(&self, name: &str) -> Vec<&str> {
+		match self.vars.get(name) {
+			Some(access) => access.writers.iter().map(|x| x.as_str()).collect(),
+			None => vec![],
+		}
+	}
+
+	/// Every variable name that is read or written somewhere, sorted.
+	pub fn names(&self) -> impl Iterator<Item = &str> {
+		self.vars.keys().map(|x| x.as_str())
+	}
+}
@@ -0,0 +1,333 @@
+// Line coverage for DM code.
+//
+// When coverage begins we walk every proc's bytecode once and hook each DbgLine, which counts a hit for its line.
+// The same walk gives us the lines that never run. Hooks share instructions, so breakpoints still work.
+// Procs whose bytecode is replaced afterwards (by a patch, say) lose their hooks and stop being counted.
+//
+// Results are written as lcov, or as Cobertura XML if the output path ends in .xml.
+// Coverage is started with the debugger's #coverage command or by setting AUXTOOLS_COVERAGE_FILE.
+
+use std::{
+	cell::UnsafeCell,
+	collections::{BTreeMap, HashMap},
+	fs::File,
+	io::{self, BufWriter, Write},
+	path::Path,
+};
+
+use auxtools::instruction_hooks::{self, InstructionHookError, InstructionHookId};
+use auxtools::*;
+
+static mut STATE: UnsafeCell<Option<State>> = UnsafeCell::new(None);
+
+// Keyed by (file string id, line)
+type Hits = HashMap<(u32, u32), u64>;
+
+struct State {
+	path: String,
+	hits: Hits,
+	hooks: Vec<InstructionHookId>,
+}
+
+#[derive(Clone, Copy)]
+enum Format {
+	Lcov,
+	Cobertura,
+}
+
+impl Format {
+	fn from_path(path: &str) -> Self {
+		match Path::new(path).extension() {
+			Some(ext) if ext.eq_ignore_ascii_case("xml") => Format::Cobertura,
+			_ => Format::Lcov,
+		}
+	}
+}
+
+// Hooks every DbgLine in the world, and returns every line with no hits yet
+fn instrument() -> Result<(Hits, Vec<InstructionHookId>), InstructionHookError> {
+	let mut lines = HashMap::new();
+	let mut hooks = vec![];
+
+	for proc in Proc::all() {
+		let bytecode = instruction_hooks::unhooked_bytecode(&proc);
+
+		let mut env = DisassembleEnv;
+		let (nodes, _error) = dmasm::disassembler::disassemble(&bytecode, &mut env);
+
+		let mut file = None;
+		for node in nodes {
+			if let dmasm::Node::Instruction(ins, debug) = node {
+				match ins {
+					dmasm::Instruction::DbgFile(_) => file = debug.bytecode.get(1).copied(),
+					dmasm::Instruction::DbgLine(line) => {
+						if let Some(file) = file {
+							lines.insert((file, line), 0);
+
+							match proc.hook_instruction(debug.offset, move |_| hit(file, line)) {
+								Ok(id) => hooks.push(id),
+								Err(e @ InstructionHookError::Unavailable(_)) => {
+									for id in hooks {
+										instruction_hooks::unhook(id);
+									}
+									return Err(e);
+								}
+								// Nothing else should go wrong with a DbgLine, and one line isn't worth failing over
+								Err(_) => {}
+							}
+						}
+					}
+					_ => {}
+				}
+			}
+		}
+	}
+
+	Ok((lines, hooks))
+}
+
+/// Starts counting line hits. Results go to `path` when coverage ends, or when DM shuts down.
+pub fn begin(path: &str) -> io::Result<()> {
+	// Fail early rather than losing the results at shutdown
+	File::create(path)?;
+
+	// Only one run at a time
+	end()?;
+
+	let (hits, hooks) =
+		instrument().map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+
+	unsafe {
+		*STATE.get() = Some(State {
+			path: path.to_owned(),
+			hits,
+			hooks,
+		});
+	}
+
+	Ok(())
+}
+
+/// Writes the results so far without stopping coverage.
+pub fn write(path: Option<&str>) -> io::Result<String> {
+	match unsafe { &*STATE.get() } {
+		Some(state) => {
+			let path = path.unwrap_or(&state.path);
+			write_report(path, &state.hits)?;
+			Ok(path.to_owned())
+		}
+
+		None => Err(io::Error::new(
+			io::ErrorKind::Other,
+			"code coverage isn't running",
+		)),
+	}
+}
+
+/// Stops counting and writes the results.
+pub fn end() -> io::Result<()> {
+	let state = unsafe { (*STATE.get()).take() };
+
+	match state {
+		Some(state) => {
+			for id in state.hooks {
+				instruction_hooks::unhook(id);
+			}
+			write_report(&state.path, &state.hits)
+		}
+		None => Ok(()),
+	}
+}
+
+fn hit(file: u32, line: u32) {
+	if let Some(state) = unsafe { &mut *STATE.get() } {
+		*state.hits.entry((file, line)).or_insert(0) += 1;
+	}
+}
+
+// Groups hits by file name, sorted by file then line so the output is stable
+fn by_file(hits: &Hits) -> BTreeMap<String, BTreeMap<u32, u64>> {
+	let mut files: BTreeMap<String, BTreeMap<u32, u64>> = BTreeMap::new();
+
+	for ((file, line), count) in hits {
+		let name = unsafe { StringRef::from_id(raw_types::strings::StringId(*file)) };
+		let name = String::from_utf8_lossy(name.data()).replace('\\', "/");
+
+		*files.entry(name).or_default().entry(*line).or_insert(0) += count;
+	}
+
+	files
+}
+
+fn write_report(path: &str, hits: &Hits) -> io::Result<()> {
+	let files = by_file(hits);
+	let mut f = BufWriter::new(File::create(path)?);
+
+	match Format::from_path(path) {
+		Format::Lcov => write_lcov(&mut f, &files)?,
+		Format::Cobertura => write_cobertura(&mut f, &files)?,
+	}
+
+	f.flush()
+}
+
+fn write_lcov<W: Write>(f: &mut W, files: &BTreeMap<String, BTreeMap<u32, u64>>) -> io::Result<()> {
+	for (file, lines) in files {
+		writeln!(f, "TN:")?;
+		writeln!(f, "SF:{}", file)?;
+
+		for (line, count) in lines {
+			writeln!(f, "DA:{},{}", line, count)?;
+		}
+
+		writeln!(f, "LF:{}", lines.len())?;
+		writeln!(f, "LH:{}", lines.values().filter(|x| **x > 0).count())?;
+		writeln!(f, "end_of_record")?;
+	}
+
+	Ok(())
+}
+
+fn xml_escape(s: &str) -> String {
+	s.replace('&', "&amp;")
+		.replace('<', "&lt;")
+		.replace('>', "&gt;")
+		.replace('"', "&quot;")
+		.replace('\'', "&apos;")
+}
+
+fn line_rate(hit: usize, total: usize) -> f64 {
+	if total == 0 {
+		return 1.0;
+	}
+
+	hit as f64 / total as f64
+}
+
+fn write_cobertura<W: Write>(
+	f: &mut W,
+	files: &BTreeMap<String, BTreeMap<u32, u64>>,
+) -> io::Result<()> {
+	let total: usize = files.values().map(|x| x.len()).sum();
+	let hit: usize = files
+		.values()
+		.map(|x| x.values().filter(|x| **x > 0).count())
+		.sum();
+
+	let timestamp = std::time::SystemTime::now()
+		.duration_since(std::time::UNIX_EPOCH)
+		.map(|x| x.as_secs())
+		.unwrap_or(0);
+
+	writeln!(f, r#"<?xml version="1.0" ?>"#)?;
+	writeln!(
+		f,
+		r#"<coverage line-rate="{:.4}" branch-rate="0" lines-covered="{}" lines-valid="{}" branches-covered="0" branches-valid="0" complexity="0" version="auxtools" timestamp="{}">"#,
+		line_rate(hit, total),
+		hit,
+		total,
+		timestamp
+	)?;
+	writeln!(f, "\t<sources><source>.</source></sources>")?;
+	writeln!(f, "\t<packages>")?;
+	writeln!(
+		f,
+		r#"		<package name="dm" line-rate="{:.4}" branch-rate="0" complexity="0">"#,
+		line_rate(hit, total)
+	)?;
+	writeln!(f, "\t\t\t<classes>")?;
+
+	for (file, lines) in files {
+		let file = xml_escape(file);
+		let file_hit = lines.values().filter(|x| **x > 0).count();
+
+		writeln!(
+			f,
+			r#"				<class name="{}" filename="{}" line-rate="{:.4}" branch-rate="0" complexity="0">"#,
+			file,
+			file,
+			line_rate(file_hit, lines.len())
+		)?;
+		writeln!(f, "\t\t\t\t\t<methods/>")?;
+		writeln!(f, "\t\t\t\t\t<lines>")?;
+
+		for (line, count) in lines {
+			writeln!(f, r#"						<line number="{}" hits="{}"/>"#, line, count)?;
+		}
+
+		writeln!(f, "\t\t\t\t\t</lines>")?;
+		writeln!(f, "\t\t\t\t</class>")?;
+	}
+
+	writeln!(f, "\t\t\t</classes>")?;
+	writeln!(f, "\t\t</package>")?;
+	writeln!(f, "\t</packages>")?;
+	writeln!(f, "</coverage>")
+}
+
+// CI has no debugger attached, so coverage can also be turned on for the whole run
+#[init(partial)]
+fn coverage_init() -> Result<(), String> {
+	if let Ok(path) = std::env::var("AUXTOOLS_COVERAGE_FILE") {
+		begin(&path).map_err(|e| format!("Couldn't start code coverage: {}", e))?;
+	}
+
+	Ok(())
+}
+
+#[shutdown]
+fn coverage_shutdown() {
+	// Nothing to report an error to at this point
+	let _ = end();
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// The writers are the only part that doesn't need BYOND running
+	fn files() -> BTreeMap<String, BTreeMap<u32, u64>> {
+		let mut files = BTreeMap::new();
+		files.insert(
+			"code/a&b.dm".to_owned(),
+			vec![(1, 3), (2, 0)].into_iter().collect(),
+		);
+		files.insert("code/c.dm".to_owned(), vec![(10, 1)].into_iter().collect());
+		files
+	}
+
+	#[test]
+	fn lcov() {
+		let mut out = vec![];
+		write_lcov(&mut out, &files()).unwrap();
+
+		assert_eq!(
+			String::from_utf8(out).unwrap(),
+			"TN:\nSF:code/a&b.dm\nDA:1,3\nDA:2,0\nLF:2\nLH:1\nend_of_record\n\
+			 TN:\nSF:code/c.dm\nDA:10,1\nLF:1\nLH:1\nend_of_record\n"
+		);
+	}
+
+	#[test]
+	fn cobertura() {
+		let mut out = vec![];
+		write_cobertura(&mut out, &files()).unwrap();
+		let out = String::from_utf8(out).unwrap();
+
+		assert!(out.contains(r#"lines-covered="2" lines-valid="3""#));
+		assert!(out.contains(
+			r#"<class name="code/a&amp;b.dm" filename="code/a&amp;b.dm" line-rate="0.5000""#
+		));
+		assert!(out.contains(r#"<line number="2" hits="0"/>"#));
+		assert!(out.trim_end().ends_with("</coverage>"));
+	}
+
+	#[test]
+	fn format_from_path() {
+		assert!(matches!(
+			Format::from_path("coverage.XML"),
+			Format::Cobertura
+		));
+		assert!(matches!(Format::from_path("coverage.info"), Format::Lcov));
+	}
+}
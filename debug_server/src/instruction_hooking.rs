@@ -1,83 +1,34 @@
-use std::{cell::UnsafeCell, ffi::c_void};
+use std::cell::RefCell;
 
-use crate::disassemble_env::DisassembleEnv;
 use crate::server_types::{BreakpointReason, ContinueKind};
 use crate::DEBUG_SERVER;
+use auxtools::instruction_hooks::{self, InstructionHookError, InstructionHookId};
 use auxtools::*;
-use detour::RawDetour;
-use lazy_static::lazy_static;
 use std::collections::HashMap;
-use std::sync::Mutex;
 
 // Could move these to dmasm
 const OPCODE_DBGLINE: u32 = 0x85;
-const OPCODE_DEBUG_BREAK: u32 = 0x1337;
-const OPCODE_DEBUG_OPERAND: u32 = 0x1338;
 
-static mut EXECUTE_INSTRUCTION: *const c_void = std::ptr::null();
-
-extern "C" {
-	// Trampoline to the original un-hooked BYOND execute_instruction code
-	static mut execute_instruction_original: *const c_void;
-
-	// Our version of execute_instruction. It hasn't got a calling convention rust knows about, so don't call it.
-	fn execute_instruction_hook();
+thread_local! {
+	static BREAKPOINTS: RefCell<HashMap<(raw_types::procs::ProcId, u32), InstructionHookId>> = RefCell::new(HashMap::new());
 }
 
-#[init(full)]
+#[init(partial)]
 fn instruction_hooking_init() -> Result<(), String> {
-	let byondcore = sigscan::Scanner::for_module(BYONDCORE).unwrap();
-
-	if cfg!(windows) {
-		let ptr = byondcore
-			.find(signature!(
-				"0F B7 48 ?? 8B 78 ?? 8B F1 8B 14 ?? 81 FA ?? ?? 00 00 0F 87 ?? ?? ?? ??"
-			))
-			.ok_or_else(|| "Couldn't find EXECUTE_INSTRUCTION")?;
-
-		unsafe {
-			EXECUTE_INSTRUCTION = ptr as *const c_void;
-		}
-	}
-
-	if cfg!(unix) {
-		let ptr = byondcore
-			.find(signature!(
-				"0F B7 47 ?? 8B 57 ?? 0F B7 D8 8B 0C ?? 81 F9 ?? ?? 00 00 77 ?? FF 24 8D ?? ?? ?? ??"
-			))
-			.ok_or_else(|| "Couldn't find EXECUTE_INSTRUCTION")?;
-
-		unsafe {
-			EXECUTE_INSTRUCTION = ptr as *const c_void;
-		}
-	}
-
-	unsafe {
-		let hook = RawDetour::new(
-			EXECUTE_INSTRUCTION as *const (),
-			execute_instruction_hook as *const (),
-		)
-		.map_err(|_| "Couldn't detour EXECUTE_INSTRUCTION")?;
-
-		hook.enable()
-			.map_err(|_| "Couldn't enable EXECUTE_INSTRUCTION detour")?;
-
-		execute_instruction_original = std::mem::transmute(hook.trampoline());
-
-		// We never remove or disable the hook, so just forget about it.
-		std::mem::forget(hook);
-	}
-
-	Ok(())
+	instruction_hooks::add_observer(handle_instruction)
+		.map(|_| ())
+		.map_err(|e| e.to_string())
 }
 
 #[shutdown]
 fn instruction_hooking_shutdown() {
 	unsafe {
 		CURRENT_ACTION = DebuggerAction::None;
-		*DEFERRED_INSTRUCTION_REPLACE.get() = None;
-		*ORIGINAL_BYTECODE.lock().unwrap() = HashMap::new();
+		DID_BREAKPOINT = false;
 	}
+
+	// auxtools restores the bytecode itself
+	BREAKPOINTS.with(|x| x.borrow_mut().clear());
 }
 
 #[derive(PartialEq, Eq, Copy, Clone)]
@@ -111,21 +62,8 @@ enum DebuggerAction {
 
 static mut CURRENT_ACTION: DebuggerAction = DebuggerAction::None;
 
-static mut DEFERRED_INSTRUCTION_REPLACE: UnsafeCell<Option<(Vec<u32>, *mut u32)>> =
-	UnsafeCell::new(None);
-
-#[derive(PartialEq, Eq, Hash)]
-struct PtrKey(usize);
-
-impl PtrKey {
-	fn new(ptr: *mut u32) -> Self {
-		unsafe { Self(std::mem::transmute(ptr)) }
-	}
-}
-
-lazy_static! {
-	static ref ORIGINAL_BYTECODE: Mutex<HashMap<PtrKey, Vec<u32>>> = Mutex::new(HashMap::new());
-}
+// Set when the debugger pauses on an instruction, so a breakpoint on the same instruction doesn't pause again
+static mut DID_BREAKPOINT: bool = false;
 
 fn is_generated_proc(ctx: *mut raw_types::procs::ExecutionContext) -> bool {
 	unsafe {
@@ -243,7 +181,9 @@ fn proc_instance_is_suspended(proc_ref: ProcInstanceRef) -> bool {
 }
 
 #[runtime_handler]
-fn handle_runtime(error: &str) {
+fn handle_runtime(event: &RuntimeEvent) {
+	let error = event.message();
+
 	unsafe {
 		let ctx = *raw_types::funcs::CURRENT_EXECUTION_CONTEXT;
 
@@ -260,21 +200,8 @@ fn handle_runtime(error: &str) {
 	}
 }
 
-// Handles any instruction BYOND tries to execute.
-// This function has to leave `*CURRENT_EXECUTION_CONTEXT` in EAX, so make sure to return it.
-#[no_mangle]
-extern "C" fn handle_instruction(
-	ctx: *mut raw_types::procs::ExecutionContext,
-) -> *const raw_types::procs::ExecutionContext {
-	// Always handle the deferred instruction replacement first - everything else will depend on it
-	unsafe {
-		let deferred = DEFERRED_INSTRUCTION_REPLACE.get();
-		if let Some((src, dst)) = &*deferred {
-			std::ptr::copy_nonoverlapping(src.as_ptr(), *dst, src.len());
-			*deferred = None;
-		}
-	}
-
+// Handles any instruction BYOND tries to execute. Breakpoints are handled afterwards by handle_breakpoint_hook.
+fn handle_instruction(ctx: *mut raw_types::procs::ExecutionContext) {
 	unsafe {
 		if let Some(server) = &mut *DEBUG_SERVER.get() {
 			if server.process() {
@@ -283,8 +210,8 @@ extern "C" fn handle_instruction(
 		}
 	}
 
-	let opcode_ptr = unsafe { (*ctx).bytecode.add((*ctx).bytecode_offset as usize) };
-	let opcode = unsafe { *opcode_ptr };
+	// Observers run before hooks are swapped out, so a breakpoint would hide a DbgLine
+	let opcode = instruction_hooks::current_opcode(ctx);
 
 	// This lets us ignore any actual breakpoints we hit if we've already paused for another reason
 	let mut did_breakpoint = false;
@@ -373,150 +300,49 @@ extern "C" fn handle_instruction(
 		}
 	}
 
-	if opcode == OPCODE_DEBUG_BREAK {
-		// We don't want to break twice when stepping on to a breakpoint
-		if !did_breakpoint {
-			unsafe {
-				CURRENT_ACTION = DebuggerAction::None;
-				CURRENT_ACTION = handle_breakpoint(ctx, BreakpointReason::Breakpoint);
-			}
-		}
-
-		// ORIGINAL_BYTECODE won't contain an entry if this breakpoint has already been removed
-		let map = ORIGINAL_BYTECODE.lock().unwrap();
-		if let Some(original) = map.get(&PtrKey::new(opcode_ptr)) {
-			unsafe {
-				let deferred_replace = DEFERRED_INSTRUCTION_REPLACE.get();
-				assert_eq!(*deferred_replace, None);
-				*deferred_replace = Some((
-					std::slice::from_raw_parts(opcode_ptr, original.len()).to_vec(),
-					opcode_ptr,
-				));
-				std::ptr::copy_nonoverlapping(original.as_ptr(), opcode_ptr, original.len());
-			}
-		}
+	unsafe {
+		DID_BREAKPOINT = did_breakpoint;
 	}
-
-	ctx
-}
-
-#[derive(Debug)]
-pub enum InstructionHookError {
-	InvalidOffset,
 }
 
-fn find_instruction<'a>(
-	env: &'a mut DisassembleEnv,
-	proc: &'a Proc,
-	offset: u32,
-) -> Option<(dmasm::Instruction, dmasm::DebugData<'a>)> {
-	let bytecode = unsafe { proc.bytecode() };
-
-	let (nodes, _error) = dmasm::disassembler::disassemble(bytecode, env);
-
-	for node in nodes {
-		if let dmasm::Node::Instruction(ins, debug) = node {
-			if debug.offset == offset {
-				return Some((ins, debug));
-			}
+fn handle_breakpoint_hook(ctx: *mut raw_types::procs::ExecutionContext) {
+	unsafe {
+		// We don't want to break twice when stepping on to a breakpoint
+		if !DID_BREAKPOINT {
+			CURRENT_ACTION = DebuggerAction::None;
+			CURRENT_ACTION = handle_breakpoint(ctx, BreakpointReason::Breakpoint);
 		}
 	}
-
-	None
 }
 
 pub fn hook_instruction(proc: &Proc, offset: u32) -> Result<(), InstructionHookError> {
-	let mut env = crate::disassemble_env::DisassembleEnv;
-	let (_, debug) =
-		find_instruction(&mut env, proc, offset).ok_or(InstructionHookError::InvalidOffset)?;
-
-	let instruction_length = debug.bytecode.len();
-
-	let bytecode;
-	let opcode;
-	let opcode_ptr;
-
-	unsafe {
-		bytecode = {
-			let (ptr, count) = proc.bytecode_mut_ptr();
-			std::slice::from_raw_parts_mut(ptr, count as usize)
-		};
-
-		opcode_ptr = bytecode.as_mut_ptr().add(offset as usize);
-		opcode = *opcode_ptr;
-	}
-
-	if opcode == OPCODE_DEBUG_BREAK {
-		return Ok(());
-	}
-
-	unsafe {
-		ORIGINAL_BYTECODE.lock().unwrap().insert(
-			PtrKey::new(opcode_ptr),
-			std::slice::from_raw_parts(opcode_ptr, instruction_length as usize).to_vec(),
-		);
-	}
+	BREAKPOINTS.with(|breakpoints| {
+		let mut breakpoints = breakpoints.borrow_mut();
 
-	bytecode[offset as usize] = OPCODE_DEBUG_BREAK;
-	for i in (offset + 1)..(offset + instruction_length as u32) {
-		bytecode[i as usize] = OPCODE_DEBUG_OPERAND;
-	}
-	Ok(())
-}
-
-#[derive(Debug)]
-pub enum InstructionUnhookError {
-	InvalidOffset,
-}
-
-pub fn unhook_instruction(proc: &Proc, offset: u32) -> Result<(), InstructionUnhookError> {
-	let mut env = crate::disassemble_env::DisassembleEnv;
-	let (_, _) =
-		find_instruction(&mut env, proc, offset).ok_or(InstructionUnhookError::InvalidOffset)?;
-
-	let opcode_ptr = unsafe {
-		let bytecode = {
-			let (ptr, count) = proc.bytecode_mut_ptr();
-			std::slice::from_raw_parts_mut(ptr, count as usize)
-		};
-
-		bytecode.as_mut_ptr().add(offset as usize)
-	};
-
-	// ORIGINAL_BYTECODE won't contain an entry if this breakpoint has already been removed
-	let mut map = ORIGINAL_BYTECODE.lock().unwrap();
-	if let Some(original) = map.get(&PtrKey::new(opcode_ptr)) {
-		unsafe {
-			let deferred = DEFERRED_INSTRUCTION_REPLACE.get();
-			if let Some((_, dst)) = *deferred {
-				if dst == opcode_ptr {
-					deferred.replace(None);
-				}
+		// Breakpoints disappear when the proc's bytecode is replaced
+		if let Some(id) = breakpoints.get(&(proc.id, offset)) {
+			if instruction_hooks::is_active(*id) {
+				return Ok(());
 			}
-			std::ptr::copy_nonoverlapping(original.as_ptr(), opcode_ptr, original.len());
 		}
 
-		map.remove(&PtrKey::new(opcode_ptr));
-	}
-
-	Ok(())
+		let id = proc.hook_instruction(offset, handle_breakpoint_hook)?;
+		breakpoints.insert((proc.id, offset), id);
+		Ok(())
+	})
 }
 
-pub fn get_hooked_offsets(proc: &Proc) -> Vec<u32> {
-	let bytecode = unsafe { proc.bytecode() };
-
-	let mut env = crate::disassemble_env::DisassembleEnv;
-	let (nodes, _error) = dmasm::disassembler::disassemble(bytecode, &mut env);
+pub fn unhook_instruction(proc: &Proc, offset: u32) -> Result<(), InstructionHookError> {
+	if !instruction_hooks::is_instruction(proc, offset) {
+		return Err(InstructionHookError::InvalidOffset);
+	}
 
-	let mut offsets = vec![];
+	let id = BREAKPOINTS.with(|breakpoints| breakpoints.borrow_mut().remove(&(proc.id, offset)));
 
-	for node in nodes {
-		if let dmasm::Node::Instruction(ins, debug) = node {
-			if ins == dmasm::Instruction::AuxtoolsDebugBreak {
-				offsets.push(debug.offset);
-			}
-		}
+	// There won't be an entry if this breakpoint has already been removed
+	if let Some(id) = id {
+		instruction_hooks::unhook(id);
 	}
 
-	offsets
+	Ok(())
 }
@@ -1,6 +1,5 @@
-mod assemble_env;
 mod ckey_override;
-mod disassemble_env;
+mod coverage;
 mod instruction_hooking;
 mod server;
 mod server_types;
@@ -18,8 +17,6 @@ mod mem_profiler_stub;
 #[cfg(not(windows))]
 use mem_profiler_stub as mem_profiler;
 
-pub(crate) use disassemble_env::DisassembleEnv;
-
 use std::{
 	cell::UnsafeCell,
 	net::{IpAddr, Ipv4Addr, SocketAddr},
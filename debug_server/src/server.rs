@@ -1,6 +1,6 @@
 use crate::mem_profiler;
 
-use super::instruction_hooking::{get_hooked_offsets, hook_instruction, unhook_instruction};
+use super::instruction_hooking::{hook_instruction, unhook_instruction};
 use std::io::{Read, Write};
 use std::sync::mpsc;
 use std::thread;
@@ -97,6 +97,30 @@ pub struct Server {
 	app: App<'static, 'static>,
 }
 
+// The response to #xref
+fn format_xref(name: &str, access: Option<&auxtools::xref::VarAccess>) -> String {
+	let access = match access {
+		Some(access) => access,
+		None => return format!("No procs use {}", name),
+	};
+
+	let mut response = format!("{} read by {} procs", name, access.readers.len());
+	for path in &access.readers {
+		response.push_str(&format!("\n\t{}", path));
+	}
+
+	response.push_str(&format!(
+		"\n{} written by {} procs",
+		name,
+		access.writers.len()
+	));
+	for path in &access.writers {
+		response.push_str(&format!("\n\t{}", path));
+	}
+
+	response
+}
+
 struct ServerThread {
 	requests: mpsc::Sender<Request>,
 }
@@ -132,6 +156,55 @@ impl Server {
 							.takes_value(true),
 					)
 			)
+			.subcommand(
+				App::new("procs")
+					.about("Lists every proc with a path matching the given pattern")
+					.after_help("* matches anything within a path segment and ** matches across segments (e.g. #procs /mob/*/Life)")
+					.arg(
+						Arg::with_name("pattern")
+							.help("Pattern to match proc paths against")
+							.takes_value(true),
+					)
+			)
+			.subcommand(
+				App::new("callgraph")
+					.about("Writes a call graph of every proc to a file")
+					.after_help("Paths ending in .json are written as JSON, anything else in Graphviz's DOT format")
+					.arg(
+						Arg::with_name("path")
+							.help("Where to output the call graph")
+							.takes_value(true),
+					)
+			)
+			.subcommand(
+				App::new("dump")
+					.about("Writes the disassembly of every proc to a directory tree mirroring proc paths")
+					.after_help("Procs are dumped as compiled, so the output of two builds can be diffed")
+					.arg(
+						Arg::with_name("dir")
+							.help("Directory to write to")
+							.takes_value(true),
+					)
+			)
+			.subcommand(
+				App::new("xref")
+					.about("Lists the procs that read or write a variable")
+					.arg(
+						Arg::with_name("name")
+							.help("Name of the variable")
+							.takes_value(true),
+					)
+			)
+			.subcommand(
+				App::new("runtimes")
+					.about("Writes the runtime aggregator's report to a file as JSON")
+					.after_help("The aggregator has to have been enabled by the game")
+					.arg(
+						Arg::with_name("path")
+							.help("Where to write the report")
+							.takes_value(true),
+					)
+			)
 			.subcommand(
 				App::new("guest_override")
 					.about("Override the CKey used by guest connections")
@@ -140,6 +213,33 @@ impl Server {
 							.takes_value(true),
 					)
 			)
+			.subcommand(
+				App::new("coverage")
+					.about("Line coverage")
+					.subcommand(
+						App::new("begin")
+							.about("Begins counting line hits. Output goes to the specified file path when coverage ends")
+							.after_help("Paths ending in .xml are written as Cobertura XML, anything else as lcov")
+							.arg(
+								Arg::with_name("path")
+									.help("Where to output coverage results")
+									.takes_value(true),
+							)
+					)
+					.subcommand(
+						App::new("write")
+							.about("Writes the coverage results so far without stopping")
+							.arg(
+								Arg::with_name("path")
+									.help("Where to output coverage results, if not the path coverage began with")
+									.takes_value(true),
+							)
+					)
+					.subcommand(
+						App::new("end")
+							.about("Finishes coverage and writes the results")
+					)
+			)
 			.subcommand(
 				App::new("mem_profiler")
 					.about("Memory profiler")
@@ -224,10 +324,10 @@ impl Server {
 				let mut current_line_number = None;
 				let mut reached_offset = false;
 
-				let bytecode = unsafe { proc.bytecode() };
+				let bytecode = auxtools::instruction_hooks::unhooked_bytecode(&proc);
 
-				let mut env = crate::disassemble_env::DisassembleEnv;
-				let (nodes, _error) = dmasm::disassembler::disassemble(bytecode, &mut env);
+				let mut env = DisassembleEnv;
+				let (nodes, _error) = dmasm::disassembler::disassemble(&bytecode, &mut env);
 
 				for node in nodes {
 					if let dmasm::Node::Instruction(ins, debug) = node {
@@ -264,10 +364,10 @@ impl Server {
 				let mut offset = None;
 				let mut at_offset = false;
 
-				let bytecode = unsafe { proc.bytecode() };
+				let bytecode = auxtools::instruction_hooks::unhooked_bytecode(&proc);
 
-				let mut env = crate::disassemble_env::DisassembleEnv;
-				let (nodes, _error) = dmasm::disassembler::disassemble(bytecode, &mut env);
+				let mut env = DisassembleEnv;
+				let (nodes, _error) = dmasm::disassembler::disassemble(&bytecode, &mut env);
 
 				for node in nodes {
 					if let dmasm::Node::Instruction(ins, debug) = node {
@@ -304,15 +404,15 @@ impl Server {
 
 	fn stringify(value: &Value) -> String {
 		if List::is_list(value) {
-			match List::from_value(value) {
-				Ok(list) => format!("/list {{len = {}}}", list.len()),
-				Err(Runtime { message }) => format!("/list (failed to get len: {:?})", message),
+			match List::from_value(value).and_then(|list| list.try_len()) {
+				Ok(len) => format!("/list {{len = {}}}", len),
+				Err(Runtime { message, .. }) => format!("/list (failed to get len: {:?})", message),
 			}
 		} else {
 			match value.to_string() {
 				Ok(v) if v.is_empty() => value.raw.to_string(),
 				Ok(value) => value,
-				Err(Runtime { message }) => {
+				Err(Runtime { message, .. }) => {
 					format!("{} -- stringify error: {:?}", value.raw, message)
 				}
 			}
@@ -347,7 +447,7 @@ impl Server {
 	fn list_to_variables(&mut self, value: &Value) -> Result<Vec<Variable>, Runtime> {
 		let state = self.state.as_ref().unwrap();
 		let list = List::from_value(value)?;
-		let len = list.len();
+		let len = list.try_len()?;
 
 		let mut variables = vec![];
 
@@ -770,6 +870,31 @@ impl Server {
 						}
 					}
 
+					("procs", Some(matches)) => match matches.value_of("pattern") {
+						Some(pattern) => self.handle_find_procs(pattern),
+						None => "no pattern provided".to_owned(),
+					},
+
+					("callgraph", Some(matches)) => match matches.value_of("path") {
+						Some(path) => self.handle_call_graph(path),
+						None => "no path provided".to_owned(),
+					},
+
+					("dump", Some(matches)) => match matches.value_of("dir") {
+						Some(dir) => self.handle_dump(dir),
+						None => "no directory provided".to_owned(),
+					},
+
+					("xref", Some(matches)) => match matches.value_of("name") {
+						Some(name) => self.handle_xref(name),
+						None => "no variable name provided".to_owned(),
+					},
+
+					("runtimes", Some(matches)) => match matches.value_of("path") {
+						Some(path) => self.handle_runtimes(path),
+						None => "no path provided".to_owned(),
+					},
+
 					("guest_override", Some(matches)) => match matches.value_of("ckey") {
 						Some(ckey) => match crate::ckey_override::override_guest_ckey(ckey) {
 							Ok(()) => "Success".to_owned(),
@@ -782,6 +907,28 @@ impl Server {
 						None => "no ckey provided".to_owned(),
 					},
 
+					("coverage", Some(matches)) => match matches.subcommand() {
+						("begin", Some(matches)) => match matches.value_of("path") {
+							Some(path) => crate::coverage::begin(path)
+								.map(|_| "Code coverage enabled".to_owned())
+								.unwrap_or_else(|e| format!("Failed: {}", e)),
+
+							None => "no path provided".to_owned(),
+						},
+
+						("write", Some(matches)) => {
+							crate::coverage::write(matches.value_of("path"))
+								.map(|path| format!("Code coverage written to {}", path))
+								.unwrap_or_else(|e| format!("Failed: {}", e))
+						}
+
+						("end", Some(_)) => crate::coverage::end()
+							.map(|_| "Code coverage disabled".to_owned())
+							.unwrap_or_else(|e| format!("Failed: {}", e)),
+
+						_ => "unknown coverage sub-command".to_owned(),
+					},
+
 					("mem_profiler", Some(matches)) => match matches.subcommand() {
 						("begin", Some(matches)) => match matches.value_of("path") {
 							Some(path) => mem_profiler::begin(path)
@@ -869,7 +1016,7 @@ impl Server {
 		};
 
 		let assembly =
-			match dmasm::assembler::assemble(&expr, &mut crate::assemble_env::AssembleEnv) {
+			match dmasm::assembler::assemble(&expr, &mut AssembleEnv) {
 				Ok(assembly) => assembly,
 				Err(err) => {
 					self.notify(format!(
@@ -896,46 +1043,60 @@ impl Server {
 		let result = match proc.call(&arg_values) {
 			Ok(res) => {
 				if let Ok(list) = res.as_list() {
-					// The rest are the potentially mutated parameters. We need to commit them to the function that called us.
-					// TODO: This sucks, obviously.
-					let len = list.len();
-					for i in 2..=len {
-						let value = list.get(i).unwrap();
-						let slot = &args[i as usize - 2].2;
-
-						unsafe {
-							match slot {
-								ArgType::Dot => {
-									let _ = Value::from_raw_owned((*ctx).dot);
-									(*ctx).dot = value.raw;
-								}
-								ArgType::Usr => {
-									let _ = Value::from_raw_owned((*instance).usr);
-									(*instance).usr = value.raw;
-								}
-								ArgType::Src => {
-									let _ = Value::from_raw_owned((*instance).src);
-									(*instance).src = value.raw;
-								}
-								ArgType::Arg(idx) => {
-									let args = (*instance).args;
-									let arg = args.add(*idx as usize);
-									let _ = Value::from_raw_owned(*arg);
-									(*arg) = value.raw;
-								}
-								ArgType::Local(idx) => {
-									let locals = (*ctx).locals;
-									let local = locals.add(*idx as usize);
-									let _ = Value::from_raw_owned(*local);
-									(*local) = value.raw;
+					// Anything the list API can't do is reported rather than panicking in the middle of the debugger
+					let commit = || -> DMResult {
+						// The rest are the potentially mutated parameters. We need to commit them to the function that called us.
+						// TODO: This sucks, obviously.
+						let len = list.try_len()?;
+						for i in 2..=len {
+							let value = list.get(i)?;
+							let slot = &args[i as usize - 2].2;
+
+							unsafe {
+								match slot {
+									ArgType::Dot => {
+										let _ = Value::from_raw_owned((*ctx).dot);
+										(*ctx).dot = value.raw;
+									}
+									ArgType::Usr => {
+										let _ = Value::from_raw_owned((*instance).usr);
+										(*instance).usr = value.raw;
+									}
+									ArgType::Src => {
+										let _ = Value::from_raw_owned((*instance).src);
+										(*instance).src = value.raw;
+									}
+									ArgType::Arg(idx) => {
+										let args = (*instance).args;
+										let arg = args.add(*idx as usize);
+										let _ = Value::from_raw_owned(*arg);
+										(*arg) = value.raw;
+									}
+									ArgType::Local(idx) => {
+										let locals = (*ctx).locals;
+										let local = locals.add(*idx as usize);
+										let _ = Value::from_raw_owned(*local);
+										(*local) = value.raw;
+									}
 								}
 							}
+
+							std::mem::forget(value);
 						}
 
-						std::mem::forget(value);
-					}
+						list.get(1)
+					};
 
-					Some(list.get(1).unwrap())
+					match commit() {
+						Ok(value) => Some(value),
+						Err(e) => {
+							self.notify(format!(
+								"couldn't read the result of expression {}: {}",
+								command, e
+							));
+							None
+						}
+					}
 				} else {
 					None
 				}
@@ -997,23 +1158,13 @@ impl Server {
 	fn handle_disassemble(&mut self, path: &str, id: u32) -> String {
 		let response = match auxtools::Proc::find_override(path, id) {
 			Some(proc) => {
-				// Make sure to temporarily remove all breakpoints in this proc
-				let breaks = get_hooked_offsets(&proc);
-
-				for offset in &breaks {
-					unhook_instruction(&proc, *offset).unwrap();
-				}
-
-				let bytecode = unsafe { proc.bytecode() };
+				// Breakpoints (and anything else hooking this proc) would get in the way
+				let bytecode = auxtools::instruction_hooks::unhooked_bytecode(&proc);
 
-				let mut env = crate::DisassembleEnv;
-				let (nodes, error) = dmasm::disassembler::disassemble(bytecode, &mut env);
+				let mut env = DisassembleEnv;
+				let (nodes, error) = dmasm::disassembler::disassemble(&bytecode, &mut env);
 				let dism = dmasm::format_disassembly(&nodes, None);
 
-				for offset in &breaks {
-					hook_instruction(&proc, *offset).unwrap();
-				}
-
 				match error {
 					Some(error) => {
 						format!("Dism for {:?}\n{}\n\tError: {:?}", proc, dism, error)
@@ -1031,6 +1182,71 @@ impl Server {
 		return response;
 	}
 
+	fn handle_call_graph(&mut self, path: &str) -> String {
+		let graph = auxtools::call_graph::CallGraph::build();
+
+		let contents = if path.ends_with(".json") {
+			graph.to_json()
+		} else {
+			graph.to_dot()
+		};
+
+		match std::fs::write(path, contents) {
+			Ok(()) => format!("Call graph written to {}", path),
+			Err(e) => format!("Failed: {}", e),
+		}
+	}
+
+	fn handle_dump(&mut self, dir: &str) -> String {
+		match auxtools::disassembly_dump::dump_world(dir) {
+			Ok(report) => {
+				let mut response = format!("{} procs written to {}", report.procs, dir);
+				for (path, error) in report.errors {
+					response.push_str(&format!("\n\t{}: {}", path, error));
+				}
+				response
+			}
+
+			Err(e) => format!("Failed: {}", e),
+		}
+	}
+
+	fn handle_xref(&mut self, name: &str) -> String {
+		let xref = auxtools::xref::VarXref::build();
+		format_xref(name, xref.get(name))
+	}
+
+	fn handle_runtimes(&mut self, path: &str) -> String {
+		if !auxtools::runtime_aggregator::is_enabled() {
+			return "The runtime aggregator isn't enabled".to_owned();
+		}
+
+		match std::fs::write(path, auxtools::runtime_aggregator::to_json()) {
+			Ok(()) => format!("Runtimes written to {}", path),
+			Err(e) => format!("Failed: {}", e),
+		}
+	}
+
+	fn handle_find_procs(&mut self, pattern: &str) -> String {
+		let procs = Proc::find_glob(pattern);
+
+		if procs.is_empty() {
+			return "No procs found".to_owned();
+		}
+
+		let mut response = format!("{} procs matching {}", procs.len(), pattern);
+		for proc in procs {
+			let overrides = Proc::override_count(&proc.path);
+			if overrides > 1 {
+				response.push_str(&format!("\n\t{} ({} definitions)", proc.path, overrides));
+			} else {
+				response.push_str(&format!("\n\t{}", proc.path));
+			}
+		}
+
+		response
+	}
+
 	// returns true if we need to break
 	fn handle_request(&mut self, request: Request) -> bool {
 		match request {
@@ -1371,3 +1587,39 @@ impl ServerThread {
 		eprintln!("Debug server thread finished");
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use auxtools::xref::VarAccess;
+
+	#[test]
+	fn xref_command_parses() {
+		let matches = Server::setup_app()
+			.get_matches_from_safe("xref health".split_ascii_whitespace())
+			.unwrap();
+
+		match matches.subcommand() {
+			("xref", Some(matches)) => assert_eq!(matches.value_of("name"), Some("health")),
+			other => panic!("parsed as {:?}", other.0),
+		}
+	}
+
+	#[test]
+	fn xref_unused() {
+		assert_eq!(format_xref("health", None), "No procs use health");
+	}
+
+	#[test]
+	fn xref_readers_and_writers() {
+		let mut access = VarAccess::default();
+		access.readers.insert("/mob/proc/examine".to_owned());
+		access.readers.insert("/mob/proc/heal".to_owned());
+		access.writers.insert("/mob/proc/heal".to_owned());
+
+		assert_eq!(
+			format_xref("health", Some(&access)),
+			"health read by 2 procs\n\t/mob/proc/examine\n\t/mob/proc/heal\nhealth written by 1 procs\n\t/mob/proc/heal"
+		);
+	}
+}
@@ -0,0 +1,65 @@
+use auxtools::dmasm::Instruction;
+use auxtools::*;
+
+fn call_target(proc: &Proc) -> DMResult<f32> {
+	proc.call(&[&Value::from(5), &Value::from(3)])?.as_number()
+}
+
+#[hook("/proc/auxtest_bytecode_patch")]
+fn test_bytecode_patch() {
+	let proc = Proc::find("/proc/auxtest_patch_target").unwrap();
+
+	// Patches should never see the debug breaks hooks are made of
+	let hook = proc
+		.hook_instruction(0, |_| {})
+		.map_err(|e| runtime!("test_bytecode_patch: {}", e))?;
+
+	let mut patch = proc
+		.patch()
+		.map_err(|e| runtime!("test_bytecode_patch: {}", e))?;
+
+	if patch
+		.instructions()
+		.any(|x| matches!(x, Instruction::AuxtoolsDebugBreak))
+	{
+		return Err(runtime!("test_bytecode_patch: patch contains a hook"));
+	}
+
+	let idx = patch
+		.position(|x| matches!(x, Instruction::Sub))
+		.ok_or_else(|| runtime!("test_bytecode_patch: couldn't find Sub"))?;
+
+	patch
+		.replace(idx, Instruction::Add)
+		.and_then(|_| patch.install(&proc))
+		.map_err(|e| runtime!("test_bytecode_patch: {}", e))?;
+
+	if instruction_hooks::is_active(hook) {
+		return Err(runtime!("test_bytecode_patch: hook survived install"));
+	}
+
+	if call_target(&proc)? != 8.0 {
+		return Err(runtime!("test_bytecode_patch: patch wasn't installed"));
+	}
+
+	// The original is still there to start again from
+	let original =
+		BytecodePatch::from_original(&proc).map_err(|e| runtime!("test_bytecode_patch: {}", e))?;
+
+	if original
+		.position(|x| matches!(x, Instruction::Sub))
+		.is_none()
+	{
+		return Err(runtime!("test_bytecode_patch: from_original saw the patch"));
+	}
+
+	proc.reset_bytecode();
+
+	if call_target(&proc)? != 2.0 {
+		return Err(runtime!(
+			"test_bytecode_patch: reset_bytecode didn't restore the proc"
+		));
+	}
+
+	Ok(Value::from(true))
+}
@@ -0,0 +1,65 @@
+use auxtools::call_graph::{Call, CallGraph, CallKind};
+use auxtools::*;
+
+fn has_call(calls: &[&Call], path: &str, kind: CallKind) -> bool {
+	calls.iter().any(|x| x.path == path && x.kind == kind)
+}
+
+#[hook("/proc/auxtest_call_graph")]
+fn test_call_graph() {
+	let graph = CallGraph::build();
+
+	let root = graph.callees("/auxtest_graph_root");
+	if !has_call(&root, "/auxtest_graph_leaf", CallKind::Global) {
+		return Err(runtime!("test_call_graph: root doesn't call leaf"));
+	}
+
+	// Datum calls link to every proc with the same name
+	for path in &["/datum/auxtest_graph/act", "/datum/auxtest_graph/child/act"] {
+		if !has_call(&root, path, CallKind::Datum) {
+			return Err(runtime!("test_call_graph: root doesn't call {}", path));
+		}
+	}
+
+	if !has_call(
+		&graph.callers("/auxtest_graph_leaf"),
+		"/auxtest_graph_root",
+		CallKind::Global,
+	) {
+		return Err(runtime!("test_call_graph: leaf isn't called by root"));
+	}
+
+	if !has_call(
+		&graph.callees("/datum/auxtest_graph/child/act"),
+		"/datum/auxtest_graph/act",
+		CallKind::Parent,
+	) {
+		return Err(runtime!(
+			"test_call_graph: ..() doesn't reach the parent type"
+		));
+	}
+
+	// The override's ..() calls the original on the same type, not a parent type
+	let overridden = graph.callees("/auxtest_overridden");
+	if overridden.len() != 1 || !has_call(&overridden, "/auxtest_overridden", CallKind::Parent) {
+		return Err(runtime!(
+			"test_call_graph: ..() in an override resolved to {:?}",
+			overridden
+		));
+	}
+
+	if !graph
+		.transitive_callers("/auxtest_graph_leaf")
+		.contains("/auxtest_graph_root")
+	{
+		return Err(runtime!(
+			"test_call_graph: transitive_callers is missing root"
+		));
+	}
+
+	if !graph.uncalled().contains(&"/auxtest_graph_root") {
+		return Err(runtime!("test_call_graph: root should be uncalled"));
+	}
+
+	Ok(Value::from(true))
+}
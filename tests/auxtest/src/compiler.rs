@@ -0,0 +1,95 @@
+use auxtools::*;
+
+#[hook("/proc/auxtest_compile_body")]
+fn test_compile_body() {
+	let proc = Proc::find("/proc/auxtest_compiled_sum").unwrap();
+
+	if let Err(e) = proc.compile_body(
+		"
+var/total = 0
+while (a > 0)
+	total += b
+	a--
+if (total > 100)
+	return -1
+else if (total == 0)
+	return
+return total
+",
+	) {
+		return Err(runtime!("test_compile_body: compile failed: {}", e));
+	}
+
+	let sum = proc.call(&[&Value::from(3), &Value::from(4)])?;
+	if sum.as_number()? != 12.0 {
+		return Err(runtime!("test_compile_body: 3 * 4 != 12"));
+	}
+
+	let sum = proc.call(&[&Value::from(50), &Value::from(4)])?;
+	if sum.as_number()? != -1.0 {
+		return Err(runtime!("test_compile_body: else if branch not taken"));
+	}
+
+	if proc.compile_body("if (a\n\treturn 1").is_ok() {
+		return Err(runtime!("test_compile_body: broken source compiled"));
+	}
+
+	// A new local needs a name BYOND already knows
+	match proc.compile_body("var/auxtest_no_variable_has_this_name = 1\nreturn 1") {
+		Err(CompileError { line: Some(1), .. }) => {}
+		_ => return Err(runtime!("test_compile_body: unknown local name compiled")),
+	}
+
+	// Calls the other compiled proc and adds a local. The line after the if starts with "else".
+	let text = Proc::find("/proc/auxtest_compiled_text").unwrap();
+	if let Err(e) = text.compile_body(
+		r#"
+var/elsewhere = "a//b" // Only this is a comment
+if (a == 1)
+	return auxtest_compiled_sum(2, 3)
+elsewhere = elsewhere + "c"
+return elsewhere
+"#,
+	) {
+		return Err(runtime!("test_compile_body: compile failed: {}", e));
+	}
+
+	if text.call(&[&Value::from(1)])?.as_number()? != 6.0 {
+		return Err(runtime!(
+			"test_compile_body: nested compiled call returned the wrong value"
+		));
+	}
+
+	if text.call(&[&Value::from(0)])?.as_string()? != "a//bc" {
+		return Err(runtime!(
+			"test_compile_body: string or identifier was mangled"
+		));
+	}
+
+	text.reset_bytecode();
+
+	// Hooks from Rust stay put
+	let hooked = Proc::find("/proc/auxtest_inc_counter").unwrap();
+	match hooked.compile_body("return 1") {
+		Err(CompileError { line: None, .. }) => {}
+		_ => {
+			return Err(runtime!(
+				"test_compile_body: compile_body replaced a Rust hook"
+			))
+		}
+	}
+
+	// Put the original back
+	proc.reset_bytecode();
+	if proc
+		.call(&[&Value::from(3), &Value::from(4)])?
+		.as_number()?
+		!= 0.0
+	{
+		return Err(runtime!(
+			"test_compile_body: reset_bytecode didn't restore the proc"
+		));
+	}
+
+	Ok(Value::from(true))
+}
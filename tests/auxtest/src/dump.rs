@@ -0,0 +1,65 @@
+use auxtools::disassembly_dump::{disassemble_proc, dump_path};
+use auxtools::*;
+use std::path::Path;
+
+fn find(path: &str, id: u32) -> DMResult<Proc> {
+	Proc::find_override(path, id).ok_or_else(|| runtime!("test_dump: couldn't find {}", path))
+}
+
+#[hook("/proc/auxtest_dump")]
+fn test_dump() {
+	let dir = Path::new("dump");
+
+	let checks = [
+		(
+			find("/proc/auxtest_overridden", 0)?,
+			"dump/auxtest_overridden.dmasm",
+		),
+		(
+			find("/proc/auxtest_overridden", 1)?,
+			"dump/auxtest_overridden.1.dmasm",
+		),
+		(
+			find("/datum/auxtest_procs/proc/plain", 0)?,
+			"dump/datum/auxtest_procs/plain.dmasm",
+		),
+		(
+			find("/datum/auxtest_procs/child/plain", 0)?,
+			"dump/datum/auxtest_procs/child/plain.dmasm",
+		),
+	];
+
+	for (proc, expected) in &checks {
+		let path = dump_path(dir, proc);
+		if path != Path::new(expected) {
+			return Err(runtime!(
+				"test_dump: {} dumps to {}, expected {}",
+				proc.path,
+				path.display(),
+				expected
+			));
+		}
+	}
+
+	// Hooks shouldn't change the output
+	let proc = find("/proc/auxtest_patch_target", 0)?;
+	let (before, error) = disassemble_proc(&proc);
+	if let Some(error) = error {
+		return Err(runtime!("test_dump: disassembly failed: {}", error));
+	}
+
+	let hook = proc
+		.hook_instruction(0, |_| {})
+		.map_err(|e| runtime!("test_dump: {}", e))?;
+	let (after, _) = disassemble_proc(&proc);
+	instruction_hooks::unhook(hook);
+
+	if before != after {
+		return Err(runtime!(
+			"test_dump: hooked disassembly differs:\n{}",
+			after
+		));
+	}
+
+	Ok(Value::from(true))
+}
@@ -0,0 +1,63 @@
+use auxtools::*;
+
+// Declared out of order, and with a higher priority than what it depends on, on purpose
+#[init(partial, priority = 100, after = "auxtest_init_first")]
+fn auxtest_init_second() -> Result<(), String> {
+	Ok(())
+}
+
+#[init(partial)]
+fn auxtest_init_first() -> Result<(), String> {
+	Ok(())
+}
+
+#[hook("/proc/auxtest_init_order")]
+fn test_init_order() {
+	let results = init_results();
+
+	let position = |name: &str| {
+		results
+			.iter()
+			.position(|x| x.name.ends_with(name) && x.outcome == InitOutcome::Succeeded)
+	};
+
+	match (
+		position("::auxtest_init_first"),
+		position("::auxtest_init_second"),
+	) {
+		(Some(first), Some(second)) if first < second => Ok(Value::from(true)),
+		_ => Err(runtime!("test_init_order: results were {:?}", results)),
+	}
+}
+
+#[hook("/proc/auxtest_init_report")]
+fn test_init_report() {
+	let report = match init_report::last_report() {
+		Some(report) => report,
+		None => return Err(runtime!("test_init_report: no report")),
+	};
+
+	let hooked = report
+		.hooks
+		.iter()
+		.any(|x| x.proc == "/proc/auxtest_init_report" && x.error.is_none());
+
+	let ran = report
+		.init_functions
+		.iter()
+		.any(|x| x.name.ends_with("::auxtest_init_first") && x.status == "succeeded");
+
+	if report.result == "SUCCESS"
+		&& report.byond_version.is_some()
+		&& report.signatures.iter().all(|x| x.found || !x.required)
+		&& hooked
+		&& ran
+	{
+		Ok(Value::from(true))
+	} else {
+		Err(runtime!(
+			"test_init_report: report was {}",
+			report.to_json()
+		))
+	}
+}
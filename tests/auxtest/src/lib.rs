@@ -1,7 +1,19 @@
 use auxtools::*;
 
+mod bytecode;
+mod call_graph;
+mod compiler;
+mod dump;
+mod init_order;
 mod lists;
+mod opcodes;
+mod optimizer;
+mod procs;
+mod runtimes;
+mod signatures;
 mod strings;
+mod vars;
+mod xref;
 
 #[hook("/proc/auxtest_inc_counter")]
 fn inc_counter() {
@@ -52,5 +52,18 @@ fn test_lists() {
 		}
 	}
 
+	// The fallible versions do the same
+	let list_c = List::try_from_iter(vec![Value::from(1), Value::from(2)])?;
+	if list_c.try_len()? != 2 || list_c.get(2)?.as_number()? != 2.0 {
+		return Err(runtime!("test_lists: list_c wasn't collected"));
+	}
+
+	list_c.try_append(&Value::from(3))?;
+	list_c.try_remove(&Value::from(1))?;
+
+	if list_c.try_len()? != 2 || list_c.get(1)?.as_number()? != 2.0 {
+		return Err(runtime!("test_lists: list_c != list(2, 3)"));
+	}
+
 	Ok(Value::from(true))
 }
@@ -0,0 +1,100 @@
+use auxtools::dmasm::Instruction;
+use auxtools::*;
+use std::cell::Cell;
+
+thread_local! {
+	static OVERFLOW_REFUSED: Cell<bool> = Cell::new(false);
+}
+
+fn square(stack: &mut custom_opcodes::OpcodeStack) -> DMResult<()> {
+	let x = stack.pop()?.as_number()?;
+	stack.push(Value::from(x * x))?;
+
+	// There's only room for what we popped
+	let refused = stack.push(Value::null()).is_err();
+	OVERFLOW_REFUSED.with(|x| x.set(refused));
+	Ok(())
+}
+
+#[hook("/proc/auxtest_custom_opcodes")]
+fn test_custom_opcodes() {
+	let op = custom_opcodes::register("auxtest_square", square)
+		.map_err(|e| runtime!("test_custom_opcodes: {}", e))?;
+
+	let proc = Proc::find("/proc/auxtest_custom_opcode_target").unwrap();
+	let mut patch = proc
+		.patch()
+		.map_err(|e| runtime!("test_custom_opcodes: {}", e))?;
+
+	// Square the argument after it is pushed to be returned
+	let idx = patch
+		.position(|x| matches!(x, Instruction::GetVar(_)))
+		.ok_or_else(|| runtime!("test_custom_opcodes: couldn't find GetVar"))?;
+
+	patch
+		.insert_custom(idx + 1, op, 1)
+		.and_then(|_| patch.install(&proc))
+		.map_err(|e| runtime!("test_custom_opcodes: {}", e))?;
+
+	if proc.call(&[&Value::from(3)])?.as_number()? != 9.0 {
+		return Err(runtime!("test_custom_opcodes: opcode wasn't dispatched"));
+	}
+
+	if !OVERFLOW_REFUSED.with(|x| x.get()) {
+		return Err(runtime!(
+			"test_custom_opcodes: push went past the popped slots"
+		));
+	}
+
+	// The patched proc can still be disassembled and patched again without losing the opcode
+	let again = proc
+		.patch()
+		.map_err(|e| runtime!("test_custom_opcodes: {}", e))?;
+
+	again
+		.install(&proc)
+		.map_err(|e| runtime!("test_custom_opcodes: {}", e))?;
+
+	if proc.call(&[&Value::from(4)])?.as_number()? != 16.0 {
+		return Err(runtime!("test_custom_opcodes: repatching lost the opcode"));
+	}
+
+	// It shows up as the call it was assembled from, but it can't be hooked like one
+	let bytecode = instruction_hooks::unhooked_bytecode(&proc);
+	let (nodes, error) = dmasm::disassembler::disassemble(&bytecode, &mut DisassembleEnv);
+	if error.is_some() {
+		return Err(runtime!(
+			"test_custom_opcodes: patched proc didn't disassemble"
+		));
+	}
+
+	let offset = nodes
+		.into_iter()
+		.find_map(|node| match node {
+			dmasm::Node::Instruction(Instruction::CallGlob(_, callee), debug)
+				if callee.0 == op.path() =>
+			{
+				Some(debug.offset)
+			}
+			_ => None,
+		})
+		.ok_or_else(|| runtime!("test_custom_opcodes: opcode is missing from the disassembly"))?;
+
+	match proc.hook_instruction(offset, |_| {}) {
+		Err(instruction_hooks::InstructionHookError::CustomOpcode) => {}
+		_ => return Err(runtime!("test_custom_opcodes: custom opcode was hooked")),
+	}
+
+	// Compiled code calls it like a proc
+	proc.compile_body("return auxtest_square(a) + 1")
+		.map_err(|e| runtime!("test_custom_opcodes: {}", e))?;
+
+	if proc.call(&[&Value::from(5)])?.as_number()? != 26.0 {
+		return Err(runtime!(
+			"test_custom_opcodes: compiled call didn't use the opcode"
+		));
+	}
+
+	proc.reset_bytecode();
+	Ok(Value::from(true))
+}
@@ -0,0 +1,102 @@
+use auxtools::dmasm::operands::Value as Operand;
+use auxtools::dmasm::Instruction;
+use auxtools::*;
+
+fn results(proc: &Proc) -> DMResult<Vec<f32>> {
+	let mut res = vec![];
+	for i in -3..10 {
+		res.push(proc.call(&[&Value::from(i)])?.as_number()?);
+	}
+	Ok(res)
+}
+
+#[hook("/proc/auxtest_optimizer")]
+fn test_optimizer() {
+	let proc = Proc::find("/proc/auxtest_optimizer_target").unwrap();
+	let expected = results(&proc)?;
+
+	let report = optimizer::optimize(&proc, Default::default())
+		.map_err(|e| runtime!("test_optimizer: {}", e))?;
+
+	if report.stripped == 0 || report.after >= report.before {
+		return Err(runtime!(
+			"test_optimizer: nothing was removed ({} -> {})",
+			report.before,
+			report.after
+		));
+	}
+
+	if results(&proc)? != expected {
+		return Err(runtime!(
+			"test_optimizer: optimized proc behaves differently"
+		));
+	}
+
+	// Optimizing again starts from the original, so it should do the same thing
+	let again = optimizer::optimize(&proc, Default::default())
+		.map_err(|e| runtime!("test_optimizer: {}", e))?;
+
+	if again.after != report.after {
+		return Err(runtime!(
+			"test_optimizer: optimizing twice gave a different result"
+		));
+	}
+
+	proc.reset_bytecode();
+	Ok(Value::from(true))
+}
+
+// DM folds constants itself, so they're put in by hand
+#[hook("/proc/auxtest_optimizer_folding")]
+fn test_optimizer_folding() {
+	let proc = Proc::find("/proc/auxtest_optimizer_fold_target").unwrap();
+	let mut patch = proc
+		.patch()
+		.map_err(|e| runtime!("test_optimizer_folding: {}", e))?;
+
+	// (a * a + a) * a becomes (2 * 3 + a) * a
+	for constant in &[2.0, 3.0] {
+		let idx = patch
+			.position(|x| matches!(x, Instruction::GetVar(_)))
+			.ok_or_else(|| runtime!("test_optimizer_folding: couldn't find GetVar"))?;
+
+		patch
+			.replace(idx, Instruction::PushVal(Operand::Number(*constant)))
+			.map_err(|e| runtime!("test_optimizer_folding: {}", e))?;
+	}
+
+	patch
+		.install(&proc)
+		.map_err(|e| runtime!("test_optimizer_folding: {}", e))?;
+	let expected = results(&proc)?;
+
+	let report = optimizer::optimize_patch(
+		&mut patch,
+		optimizer::OptimizeOptions {
+			fold_constants: true,
+			strip_debug_info: false,
+		},
+	);
+
+	if report.folded != 1 || report.after != report.before - 2 {
+		return Err(runtime!(
+			"test_optimizer_folding: folded {} ({} -> {})",
+			report.folded,
+			report.before,
+			report.after
+		));
+	}
+
+	patch
+		.install(&proc)
+		.map_err(|e| runtime!("test_optimizer_folding: {}", e))?;
+
+	if results(&proc)? != expected {
+		return Err(runtime!(
+			"test_optimizer_folding: folded proc behaves differently"
+		));
+	}
+
+	proc.reset_bytecode();
+	Ok(Value::from(true))
+}
@@ -0,0 +1,121 @@
+use auxtools::*;
+
+fn find(name: &str) -> DMResult<Proc> {
+	Proc::find(format!("/datum/auxtest_procs/{}", name))
+		.ok_or_else(|| runtime!("test_proc_flags: couldn't find {}", name))
+}
+
+#[hook("/proc/auxtest_proc_flags")]
+fn test_proc_flags() {
+	let plain = find("plain")?.flags();
+	if plain.hidden() || !plain.popup_menu() || plain.instant() || plain.background() {
+		return Err(runtime!(
+			"test_proc_flags: plain has flags set ({:#x})",
+			plain.bits()
+		));
+	}
+
+	// Each of these has exactly one setting changed from the defaults
+	let checks: &[(&str, fn(&ProcFlags) -> bool)] = &[
+		("hidden", |x| {
+			x.hidden() && x.popup_menu() && !x.instant() && !x.background()
+		}),
+		("no_popup", |x| {
+			!x.hidden() && !x.popup_menu() && !x.instant() && !x.background()
+		}),
+		("instant", |x| {
+			!x.hidden() && x.popup_menu() && x.instant() && !x.background()
+		}),
+		("background", |x| {
+			!x.hidden() && x.popup_menu() && !x.instant() && x.background()
+		}),
+	];
+
+	for (name, check) in checks {
+		let flags = find(name)?.flags();
+		if !check(&flags) {
+			return Err(runtime!(
+				"test_proc_flags: {} has the wrong flags ({:#x})",
+				name,
+				flags.bits()
+			));
+		}
+	}
+
+	if !find("hidden")?.is_hidden() || find("plain")?.is_hidden() {
+		return Err(runtime!("test_proc_flags: is_hidden is wrong"));
+	}
+
+	if !find("described")?.is_verb() || find("plain")?.is_verb() {
+		return Err(runtime!("test_proc_flags: is_verb is wrong"));
+	}
+
+	let described = find("described")?;
+	let settings = (described.name(), described.desc(), described.category());
+	match settings {
+		(Some(name), Some(desc), Some(category))
+			if name.data() == b"Described"
+				&& desc.data() == b"auxtest desc"
+				&& category.data() == b"Auxtest" => {}
+		_ => {
+			return Err(runtime!(
+				"test_proc_flags: described has the wrong settings"
+			))
+		}
+	}
+
+	Ok(Value::from(true))
+}
+
+fn paths(procs: Vec<Proc>) -> Vec<String> {
+	procs.into_iter().map(|x| x.path).collect()
+}
+
+#[hook("/proc/auxtest_proc_registry")]
+fn test_proc_registry() {
+	if Proc::override_count("/proc/auxtest_overridden") != 2
+		|| Proc::override_count("/proc/auxtest_does_not_exist") != 0
+	{
+		return Err(runtime!("test_proc_registry: override_count is wrong"));
+	}
+
+	let prefixed = paths(Proc::find_prefix("/datum/auxtest_procs/child/"));
+	if prefixed != ["/datum/auxtest_procs/child/plain"] {
+		return Err(runtime!(
+			"test_proc_registry: find_prefix found {:?}",
+			prefixed
+		));
+	}
+
+	// A single * stays within its path segment
+	let globbed = paths(Proc::find_glob("/datum/*/plain"));
+	if globbed != ["/datum/auxtest_procs/plain"] {
+		return Err(runtime!(
+			"test_proc_registry: find_glob found {:?}",
+			globbed
+		));
+	}
+
+	let deep = Proc::find_glob("/datum/**/plain");
+	if deep.len() != 2 {
+		return Err(runtime!("test_proc_registry: ** didn't cross segments"));
+	}
+
+	let own = paths(Proc::defined_on("/datum/auxtest_procs/child", false));
+	if own != ["/datum/auxtest_procs/child/plain"] {
+		return Err(runtime!("test_proc_registry: defined_on found {:?}", own));
+	}
+
+	// The child's plain() hides its parent's
+	let inherited = paths(Proc::defined_on("/datum/auxtest_procs/child", true));
+	if !inherited.contains(&"/datum/auxtest_procs/background".to_owned())
+		|| inherited.contains(&"/datum/auxtest_procs/plain".to_owned())
+	{
+		return Err(runtime!(
+			"test_proc_registry: defined_on(inherited) found {:?}",
+			inherited
+		));
+	}
+
+	Ok(Value::from(true))
+}
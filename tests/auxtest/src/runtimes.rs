@@ -0,0 +1,279 @@
+use auxtools::*;
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[hook("/proc/auxtest_runtimes")]
+fn test_runtimes() {
+	// Runtimes have to be able to cross threads, sources included
+	assert_send_sync::<Runtime>();
+
+	let runtime = Runtime::new("something broke")
+		.with_proc("/proc/auxtest_runtimes")
+		.with_location("code/test.dm", 12)
+		.with_dm_message("the details");
+
+	let exception = runtime.to_exception()?;
+	if !exception.is_exact_type("/exception") {
+		return Err(runtime!(
+			"test_runtimes: to_exception didn't create an /exception"
+		));
+	}
+
+	let read = Runtime::from_exception(&exception);
+	if read.message != runtime.message
+		|| read.proc() != runtime.proc()
+		|| read.file() != Some("code/test.dm")
+		|| read.line() != Some(12)
+		|| read.dm_message() != Some("the details")
+	{
+		return Err(runtime!(
+			"test_runtimes: exception didn't round-trip: {:?}",
+			read
+		));
+	}
+
+	if runtime.to_string()
+		!= "something broke: the details (/proc/auxtest_runtimes, code/test.dm:12)"
+	{
+		return Err(runtime!(
+			"test_runtimes: unexpected display {:?}",
+			runtime.to_string()
+		));
+	}
+
+	let caused =
+		Runtime::new("outer").with_source(std::io::Error::new(std::io::ErrorKind::Other, "inner"));
+	match std::error::Error::source(&caused) {
+		Some(source) if source.to_string() == "inner" => {}
+		other => {
+			return Err(runtime!("test_runtimes: unexpected source {:?}", other));
+		}
+	}
+
+	Ok(Value::from(true))
+}
+
+#[hook("/proc/auxtest_catch_runtimes")]
+fn test_catch_runtimes() {
+	let crash = Proc::find("/proc/auxtest_crash").unwrap();
+
+	let err = match crash.call_checked(&[&Value::from_string("auxtest expected: boom")?]) {
+		Ok(_) => return Err(runtime!("test_catch_runtimes: call didn't fail")),
+		Err(e) => e,
+	};
+
+	if !err.message.contains("boom") || err.proc() != Some("/proc/auxtest_crash") {
+		return Err(runtime!("test_catch_runtimes: unexpected error {:?}", err));
+	}
+
+	let (_, runtimes) = collect_runtimes(|| {
+		let _ = crash.call_checked(&[&Value::from_string("auxtest expected: one")?]);
+		Ok::<_, Runtime>(())
+	});
+
+	// The call's own scope got it
+	if !runtimes.is_empty() {
+		return Err(runtime!(
+			"test_catch_runtimes: outer scope saw {:?}",
+			runtimes
+		));
+	}
+
+	let nested = Proc::find("/proc/auxtest_crash_nested").unwrap();
+
+	// Plain calls still return what the proc did
+	let (res, runtimes) = collect_runtimes(|| nested.call(&[]));
+	if !matches!(&res, Ok(value) if value.as_number().ok() == Some(1.0)) || runtimes.len() != 1 {
+		return Err(runtime!(
+			"test_catch_runtimes: plain call gave {:?} and {:?}",
+			res,
+			runtimes
+		));
+	}
+
+	let res = catch_runtimes(|| nested.call(&[]));
+	match res {
+		Err(e) if e.message.contains("nested") => Ok(Value::from(true)),
+		res => Err(runtime!("test_catch_runtimes: nested crash gave {:?}", res)),
+	}
+}
+
+#[hook("/proc/auxtest_panic")]
+fn panicking_hook() {
+	panic!("auxtest expected: panic");
+}
+
+#[hook("/proc/auxtest_panics")]
+fn test_panics() {
+	let proc = Proc::find("/proc/auxtest_panic").unwrap();
+	panics::set_failure_limit(Some(2));
+
+	for _ in 0..2 {
+		match proc.call_checked(&[]) {
+			Err(e) if e.message.contains("auxtest expected: panic") => {}
+			res => {
+				panics::set_failure_limit(None);
+				return Err(runtime!("test_panics: panicking hook gave {:?}", res));
+			}
+		}
+	}
+
+	panics::set_failure_limit(None);
+
+	// The hook should be gone now, leaving the DM proc
+	match proc.call(&[]) {
+		Ok(value) if value.as_number()? == 5.0 => Ok(Value::from(true)),
+		res => Err(runtime!("test_panics: hook wasn't disabled: {:?}", res)),
+	}
+}
+
+#[hook("/proc/auxtest_failing_hook")]
+fn failing_hook() {
+	Err(Runtime::new("auxtest expected: failure")
+		.with_source(std::io::Error::new(std::io::ErrorKind::Other, "the cause")))
+}
+
+#[hook("/proc/auxtest_hook_errors")]
+fn test_hook_errors() {
+	use std::cell::RefCell;
+	use std::rc::Rc;
+
+	let seen = Rc::new(RefCell::new(None));
+	let seen_by_handler = seen.clone();
+
+	set_hook_error_handler(move |e| {
+		seen_by_handler.replace(Some((
+			e.proc.to_owned(),
+			e.fn_name,
+			e.runtime.message.clone(),
+			std::error::Error::source(&e.runtime).map(|x| x.to_string()),
+		)));
+	});
+
+	let res = Proc::find("/proc/auxtest_failing_hook").unwrap().call(&[]);
+	reset_hook_error_handler();
+
+	// Our handler doesn't runtime, so the hooked proc just returns null
+	if !matches!(&res, Ok(value) if value.raw.tag == raw_types::values::ValueTag::Null) {
+		return Err(runtime!("test_hook_errors: call gave {:?}", res));
+	}
+
+	let expected = (
+		"/proc/auxtest_failing_hook".to_owned(),
+		Some("failing_hook"),
+		"auxtest expected: failure".to_owned(),
+		Some("the cause".to_owned()),
+	);
+
+	match seen.borrow_mut().take() {
+		Some(seen) if seen == expected => {}
+		seen => return Err(runtime!("test_hook_errors: handler saw {:?}", seen)),
+	}
+
+	// The default handler reports the function name and the whole source chain
+	match Proc::find("/proc/auxtest_failing_hook")
+		.unwrap()
+		.call_checked(&[])
+	{
+		Err(e)
+			if e.message
+				.contains("failing_hook: auxtest expected: failure")
+				&& e.message.contains("caused by: the cause") =>
+		{
+			Ok(Value::from(true))
+		}
+		res => Err(runtime!("test_hook_errors: default handler gave {:?}", res)),
+	}
+}
+
+thread_local! {
+	static SEEN_EVENTS: std::cell::RefCell<Vec<(Option<String>, usize)>> = std::cell::RefCell::new(vec![]);
+}
+
+#[runtime_handler]
+fn record_runtime(event: &RuntimeEvent) {
+	if !event.message().contains("auxtest expected: suppressed") {
+		return;
+	}
+
+	let frames = event.call_stacks().active.len();
+	SEEN_EVENTS.with(|x| x.borrow_mut().push((event.proc().map(|x| x.path), frames)));
+	event.suppress_logging();
+}
+
+#[hook("/proc/auxtest_runtime_events")]
+fn test_runtime_events() {
+	let errors_before = Value::globals().get_number(byond_string!("auxtest_world_errors"))?;
+
+	let crash = Proc::find("/proc/auxtest_crash").unwrap();
+	let _ = crash.call(&[&Value::from_string("auxtest expected: suppressed")?]);
+
+	let events = SEEN_EVENTS.with(|x| std::mem::take(&mut *x.borrow_mut()));
+	match events.as_slice() {
+		[(Some(proc), frames)] if proc == "/proc/auxtest_crash" && *frames >= 2 => {}
+		_ => return Err(runtime!("test_runtime_events: handler saw {:?}", events)),
+	}
+
+	let errors_after = Value::globals().get_number(byond_string!("auxtest_world_errors"))?;
+
+	if errors_after != errors_before {
+		return Err(runtime!(
+			"test_runtime_events: world/Error was still called"
+		));
+	}
+
+	Ok(Value::from(true))
+}
+
+#[hook("/proc/auxtest_runtime_aggregator")]
+fn test_runtime_aggregator() {
+	runtime_aggregator::enable(runtime_aggregator::AggregatorOptions {
+		log_limit: 1,
+		window: std::time::Duration::from_secs(3600),
+		max_groups: 2,
+	});
+
+	let crash = Proc::find("/proc/auxtest_crash").unwrap();
+	for _ in 0..3 {
+		let _ = crash.call(&[&Value::from_string("auxtest expected: repeated")?]);
+	}
+
+	let groups = runtime_aggregator::groups();
+	let json = runtime_aggregator::to_json();
+
+	match groups.as_slice() {
+		[group]
+			if group.count == 3
+				&& group.suppressed == 2
+				&& group.proc.as_deref() == Some("/proc/auxtest_crash")
+				&& json.contains("auxtest expected: repeated") => {}
+		_ => {
+			runtime_aggregator::disable();
+			return Err(runtime!(
+				"test_runtime_aggregator: groups were {:?}",
+				groups
+			));
+		}
+	}
+
+	// Only two groups fit, so the one seen longest ago goes
+	for message in &["auxtest expected: first", "auxtest expected: second"] {
+		let _ = crash.call(&[&Value::from_string(message)?]);
+	}
+
+	let groups = runtime_aggregator::groups();
+	runtime_aggregator::disable();
+
+	let mut messages: Vec<&str> = groups.iter().map(|x| x.message.as_str()).collect();
+	messages.sort_unstable();
+
+	match messages.as_slice() {
+		[first, second] if first.contains("first") && second.contains("second") => {
+			Ok(Value::from(true))
+		}
+		_ => Err(runtime!(
+			"test_runtime_aggregator: groups after eviction were {:?}",
+			groups
+		)),
+	}
+}
@@ -0,0 +1,82 @@
+use auxtools::*;
+use std::fs;
+
+#[hook("/proc/auxtest_signature_db")]
+fn test_signature_db() {
+	let path = std::env::temp_dir().join("auxtest_signatures.json");
+
+	let other_os = if cfg!(windows) { "linux" } else { "windows" };
+
+	// None of the auxtest_never entries apply, and neither does the second auxtest_sig
+	fs::write(
+		&path,
+		format!(
+			r#"[
+				{{ "name": "auxtest_sig", "pattern": "55 8B ?? EC" }},
+				{{ "name": "auxtest_sig", "max_build": 1, "pattern": "00" }},
+				{{ "name": "auxtest_never", "os": "{}", "pattern": "00" }},
+				{{ "name": "auxtest_never", "min_build": 99999, "pattern": "00" }}
+			]"#,
+			other_os
+		),
+	)
+	.unwrap();
+
+	let loaded = signature_db::load(&path);
+	let sig = signature_db::get("auxtest_sig");
+	let never = signature_db::get("auxtest_never");
+	let builtin = signature_db::get("to_string");
+
+	fs::write(&path, r#"[{ "name": "auxtest_bad", "pattern": "55 8G" }]"#).unwrap();
+	let bad = signature_db::load(&path);
+	let still_loaded = signature_db::get("auxtest_sig").is_some();
+
+	signature_db::clear();
+	let _ = fs::remove_file(&path);
+
+	if loaded.is_err()
+		|| sig != Some(vec![Some(0x55), Some(0x8B), None, Some(0xEC)])
+		|| never.is_some()
+		|| builtin.is_some()
+		|| bad.is_ok()
+		|| !still_loaded
+	{
+		return Err(runtime!(
+			"test_signature_db: loaded {:?}, got {:?}, {:?}, {:?}, bad file {:?}",
+			loaded,
+			sig,
+			never,
+			builtin,
+			bad
+		));
+	}
+
+	Ok(Value::from(true))
+}
+
+// do_tests loads a file overriding variable_names, from a quoted path with a space in it
+#[hook("/proc/auxtest_signature_override")]
+fn test_signature_override() {
+	let report = match init_report::last_report() {
+		Some(report) => report,
+		None => return Err(runtime!("test_signature_override: no report")),
+	};
+
+	let overridden = report
+		.signatures
+		.iter()
+		.any(|x| x.name == "variable_names" && x.external && x.found);
+
+	let offset = signature_db::get_with_offset("variable_names").map(|(_, offset)| offset);
+
+	// Whether the offset was applied to the match is covered by auxtest_vars, which needs variable_names
+	if !overridden || offset != Some(1) {
+		return Err(runtime!(
+			"test_signature_override: offset {:?}, report was {}",
+			offset,
+			report.to_json()
+		));
+	}
+
+	Ok(Value::from(true))
+}
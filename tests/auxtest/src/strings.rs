@@ -117,3 +117,92 @@ fn test_strings() {
 		Ok(Value::from(true))
 	}
 }
+
+#[hook("/proc/auxtest_text_macros")]
+fn test_text_macros(improper_name: Value) {
+	let improper_name = StringRef::from_value(improper_name)
+		.ok_or_else(|| runtime!("test_text_macros: improper_name is not a string"))?;
+
+	let built = TextBuilder::new()
+		.push(TextMacro::Improper)
+		.text("crowbar")
+		.to_string_ref()?;
+
+	// Our encoding should be identical to the one the compiler produced
+	if built.data() != improper_name.data() {
+		return Err(runtime!(
+			"test_text_macros: {:?} != {:?}",
+			built,
+			improper_name
+		));
+	}
+
+	if built.strip_macros() != "crowbar" {
+		return Err(runtime!("test_text_macros: strip_macros() != crowbar"));
+	}
+
+	let tokens = improper_name.tokens();
+	if tokens.len() != 2 || StringRef::from_tokens(&tokens)?.data() != improper_name.data() {
+		return Err(runtime!("test_text_macros: tokens did not round-trip"));
+	}
+
+	Ok(Value::from(true))
+}
+
+#[hook("/proc/auxtest_interned_strings")]
+fn test_interned_strings() {
+	let name = format!("auxtest_{}", "interned");
+	let before = InternedString::interned_count();
+
+	let a = InternedString::intern(&name)?;
+	let b = InternedString::intern(&name)?;
+
+	if a.get_id().0 != b.get_id().0 || InternedString::interned_count() != before + 1 {
+		return Err(runtime!(
+			"test_interned_strings: same string was interned twice"
+		));
+	}
+
+	// Full caches still hand out strings, they just don't remember them
+	InternedString::set_capacity(Some(before + 1));
+	let c = InternedString::intern("auxtest_not_cached")?;
+	InternedString::set_capacity(None);
+
+	if c.data() != b"auxtest_not_cached" || InternedString::interned_count() != before + 1 {
+		return Err(runtime!(
+			"test_interned_strings: capacity was not respected"
+		));
+	}
+
+	Ok(Value::from(true))
+}
+
+#[hook("/proc/auxtest_string_table")]
+fn test_string_table() {
+	if !auxtools::strings::available() {
+		return Err(runtime!(
+			"test_string_table: string table size wasn't found"
+		));
+	}
+
+	let string = StringRef::new("auxtest_string_table_entry")?;
+
+	let found = auxtools::strings::find_by_prefix("auxtest_string_table_");
+	let entry = found
+		.iter()
+		.find(|x| x.id.0 == string.get_id().0)
+		.ok_or_else(|| runtime!("test_string_table: string was not found by prefix"))?;
+
+	if entry.ref_count != 1 {
+		return Err(runtime!("test_string_table: ref_count != 1"));
+	}
+
+	let stats = auxtools::strings::stats();
+	let bucketed: usize = stats.buckets.iter().map(|x| x.count).sum();
+
+	if stats.count == 0 || bucketed != stats.count {
+		return Err(runtime!("test_string_table: buckets don't add up"));
+	}
+
+	Ok(Value::from(true))
+}
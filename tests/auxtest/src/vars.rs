@@ -0,0 +1,33 @@
+use auxtools::*;
+
+#[hook("/proc/auxtest_vars")]
+fn test_vars(holder: Value) {
+	if byond_var!("health").id().is_none() {
+		return Err(runtime!("test_vars: health wasn't resolved"));
+	}
+
+	if holder.get_var(byond_var!("health"))?.as_number()? != 10.0 {
+		return Err(runtime!("test_vars: holder.health != 10"));
+	}
+
+	holder.set_var(byond_var!("health"), 25)?;
+
+	// Both paths should agree
+	if holder.get_number(byond_string!("health"))? != 25.0 {
+		return Err(runtime!("test_vars: holder.health != 25 after set_var"));
+	}
+
+	// Nothing in the world is called this, so we should fall back to the slow path and fail
+	if byond_var!("auxtest_var_that_does_not_exist").id().is_some() {
+		return Err(runtime!("test_vars: unknown variable was resolved"));
+	}
+
+	if holder
+		.get_var(byond_var!("auxtest_var_that_does_not_exist"))
+		.is_ok()
+	{
+		return Err(runtime!("test_vars: read of unknown variable succeeded"));
+	}
+
+	Ok(Value::from(true))
+}
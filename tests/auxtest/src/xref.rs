@@ -0,0 +1,42 @@
+use auxtools::xref::VarXref;
+use auxtools::*;
+
+#[hook("/proc/auxtest_xref")]
+fn test_xref() {
+	let xref = VarXref::build();
+	let readers = xref.readers("auxtest_xref_count");
+	let writers = xref.writers("auxtest_xref_count");
+
+	// += and ++ both read and write
+	for name in &["read", "add", "inc"] {
+		let path = format!("/datum/auxtest_xref/{}", name);
+		if !readers.contains(&path.as_str()) {
+			return Err(runtime!(
+				"test_xref: {} isn't a reader ({:?})",
+				name,
+				readers
+			));
+		}
+	}
+
+	for name in &["write", "add", "inc"] {
+		let path = format!("/datum/auxtest_xref/{}", name);
+		if !writers.contains(&path.as_str()) {
+			return Err(runtime!(
+				"test_xref: {} isn't a writer ({:?})",
+				name,
+				writers
+			));
+		}
+	}
+
+	if writers.contains(&"/datum/auxtest_xref/read") {
+		return Err(runtime!("test_xref: read is a writer"));
+	}
+
+	if !xref.names().any(|x| x == "auxtest_xref_count") {
+		return Err(runtime!("test_xref: names is missing auxtest_xref_count"));
+	}
+
+	Ok(Value::from(true))
+}